@@ -0,0 +1,62 @@
+use std::sync::Arc;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use wavetable_synth::lfo::{LfoShape, LfoTarget, LFO};
+use wavetable_synth::preset::Preset;
+use wavetable_synth::render::{self, EventKind, MidiEvent};
+use wavetable_synth::tuning::Tuning;
+use wavetable_synth::wavetable::Wavetable;
+
+const SAMPLE_RATE: u32 = 44100;
+const BLOCK: usize = 512; // Frames per render call
+
+fn oscillator(c: &mut Criterion) {
+    let wavetable = Arc::new(Wavetable::basic_shapes());
+    let mut buffer = vec![0.0; BLOCK * 2];
+
+    for voices in [1, 8] {
+        let preset = Preset {
+            unison_voices: voices,
+            unison_detune: 20.0,
+            unison_spread: 0.5,
+            ..Preset::default()
+        };
+        let template = preset.oscillator(SAMPLE_RATE, Arc::clone(&wavetable));
+        let mut voice = preset.voice(&template, 220.0, 100, 0.0, 0);
+        c.bench_function(&format!("oscillator render, {} unison voices", voices), |b| {
+            b.iter(|| voice.render(black_box(&mut buffer)))
+        });
+    }
+}
+
+fn lfo(c: &mut Criterion) {
+    let mut lfo = LFO::new(SAMPLE_RATE, LfoShape::Sine, 5.0, 0.5, LfoTarget::Pitch);
+    c.bench_function("lfo next_value", |b| b.iter(|| black_box(lfo.next_value())));
+}
+
+// A second of sixteen held notes through the default preset, the same work
+// the synth does for full polyphony.
+fn polyphony(c: &mut Criterion) {
+    let wavetable = Arc::new(Wavetable::basic_shapes());
+    let preset = Preset::default();
+    let tuning = Tuning::default();
+    let mut events: Vec<MidiEvent> = (0..16)
+        .map(|voice| MidiEvent {
+            time: 0.0,
+            channel: 0,
+            kind: EventKind::Note { key: 48 + voice, velocity: 100 },
+        })
+        .collect();
+    events.extend((0..16).map(|voice| MidiEvent {
+        time: 1.0,
+        channel: 0,
+        kind: EventKind::Note { key: 48 + voice, velocity: 0 },
+    }));
+
+    c.bench_function("render 16 voices for 1 s", |b| {
+        b.iter(|| render::render_events(&events, &preset, &tuning, Arc::clone(&wavetable), None, SAMPLE_RATE))
+    });
+}
+
+criterion_group!(benches, oscillator, lfo, polyphony);
+criterion_main!(benches);
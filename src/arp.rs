@@ -0,0 +1,114 @@
+use serde::{Deserialize, Serialize};
+
+use crate::lfo;
+
+const ARP_KEYS: usize = 32; // Keys held at once that the arpeggiator remembers
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ArpPattern {
+    Up,
+    Down,
+    UpDown, // Up then back down, without repeating the top and bottom notes
+    Random,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ArpNote {
+    pub channel: u8,
+    pub key: u8,
+    pub frequency: f32,
+    pub velocity: u8,
+}
+
+// The held keys, lowest first, and where the pattern has got to. The synth
+// keeps time and asks for the next note on every step.
+pub struct Arpeggiator {
+    held: Vec<ArpNote>,
+    step: usize,
+    seed: u32,
+}
+
+impl Arpeggiator {
+    pub fn new() -> Arpeggiator {
+        Arpeggiator {
+            held: Vec::with_capacity(ARP_KEYS),
+            step: 0,
+            seed: 0,
+        }
+    }
+
+    // Adds a key, replacing it if it's already held. Past ARP_KEYS the new
+    // key is ignored.
+    pub fn press(&mut self, note: ArpNote) {
+        self.release(note.channel, note.key);
+        if self.held.len() == ARP_KEYS {
+            return;
+        }
+        let index = self.held.partition_point(|held| held.key <= note.key);
+        self.held.insert(index, note);
+    }
+
+    // Returns whether the key was held.
+    pub fn release(&mut self, channel: u8, key: u8) -> bool {
+        let before = self.held.len();
+        self.held.retain(|held| (held.channel, held.key) != (channel, key));
+        self.held.len() != before
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.held.is_empty()
+    }
+
+    // Lets go of every key and starts the pattern over.
+    pub fn clear(&mut self) {
+        self.held.clear();
+        self.step = 0;
+    }
+
+    // The note for the next step, running `pattern` across the held keys
+    // repeated over `octaves` octaves. None once no keys are held, which
+    // also starts the pattern over.
+    pub fn next_note(&mut self, pattern: ArpPattern, octaves: u8) -> Option<ArpNote> {
+        if self.held.is_empty() {
+            self.step = 0;
+            return None;
+        }
+        let length = self.held.len() * octaves.clamp(1, 4) as usize;
+        let step = self.step;
+        self.step += 1;
+        let index = match pattern {
+            ArpPattern::Up => step % length,
+            ArpPattern::Down => length - 1 - step % length,
+            ArpPattern::UpDown if length == 1 => 0,
+            ArpPattern::UpDown => {
+                let position = step % (2 * length - 2);
+                if position < length {
+                    position
+                } else {
+                    2 * length - 2 - position
+                }
+            }
+            ArpPattern::Random => {
+                let random = lfo::random(&mut self.seed) * 0.5 + 0.5;
+                ((random * length as f32) as usize).min(length - 1)
+            }
+        };
+
+        let note = self.held[index % self.held.len()];
+        let octave = (index / self.held.len()) as u8;
+        match note.key.checked_add(12 * octave).filter(|&key| key <= 127) {
+            Some(key) => Some(ArpNote {
+                key,
+                frequency: note.frequency * (1 << octave) as f32,
+                ..note
+            }),
+            None => Some(note),
+        }
+    }
+}
+
+impl Default for Arpeggiator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
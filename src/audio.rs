@@ -0,0 +1,184 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use rodio::cpal::traits::{HostTrait, StreamTrait};
+use rodio::cpal::{
+    BufferSize, OutputCallbackInfo, Sample, SampleFormat, SampleRate, Stream, StreamConfig, SupportedBufferSize,
+};
+use rodio::{cpal, Device, DeviceTrait, OutputStream, OutputStreamHandle, StreamError};
+use tracing::{error, warn};
+
+use crate::error::SynthError;
+
+pub const MIN_BUFFER_SIZE: u32 = 64;
+pub const MAX_BUFFER_SIZE: u32 = 2048;
+
+// Names of the output devices on the default host, skipping any that can't
+// report one.
+pub fn output_devices() -> Result<Vec<String>, SynthError> {
+    let devices = cpal::default_host().output_devices()?;
+    Ok(devices.filter_map(|device| device.name().ok()).collect())
+}
+
+// Opens `name`, or the default output device, and returns the stream with the
+// device's own sample rate. Rendering at that rate keeps rodio from
+// resampling every voice on the way out.
+pub fn open_output(name: Option<&str>) -> Result<(OutputStream, OutputStreamHandle, u32), SynthError> {
+    let device = find_device(name)?;
+    let sample_rate = device.default_output_config()?.sample_rate().0;
+    let (stream, stream_handle) = OutputStream::try_from_device(&device)?;
+    Ok((stream, stream_handle, sample_rate))
+}
+
+fn find_device(name: Option<&str>) -> Result<Device, SynthError> {
+    let host = cpal::default_host();
+    match name {
+        Some(name) => host
+            .output_devices()?
+            .find(|device| device.name().is_ok_and(|device_name| device_name == name))
+            .ok_or_else(|| SynthError::NoAudioDevice(String::from(name))),
+        None => host
+            .default_output_device()
+            .ok_or_else(|| SynthError::NoAudioDevice(String::from("default"))),
+    }
+}
+
+// An output device the synth plays straight into, bypassing rodio's mixer so
+// the buffer size can be chosen: smaller for less latency, larger for fewer
+// dropouts on a busy machine.
+pub struct Output {
+    device: Device,
+    config: StreamConfig,
+    sample_format: SampleFormat,
+    failed: Arc<AtomicBool>,
+    latency: Arc<AtomicU32>, // Microseconds, 0 until the device has reported it
+}
+
+impl Output {
+    // Uses the device's default sample rate and buffer size for any left as
+    // None. A sample rate the device can't run at falls back to its default,
+    // and a buffer size is kept within what it supports.
+    pub fn open(name: Option<&str>, sample_rate: Option<u32>, buffer_size: Option<u32>) -> Result<Output, SynthError> {
+        let device = find_device(name)?;
+        let mut supported = device.default_output_config()?;
+        if let Some(rate) = sample_rate {
+            let matching = device
+                .supported_output_configs()
+                .map_err(StreamError::SupportedStreamConfigsError)?
+                .find(|range| {
+                    range.channels() == supported.channels()
+                        && range.sample_format() == supported.sample_format()
+                        && (range.min_sample_rate().0..=range.max_sample_rate().0).contains(&rate)
+                });
+            match matching {
+                Some(range) => supported = range.with_sample_rate(SampleRate(rate)),
+                None => warn!("the device can't run at {} Hz, using its default rate", rate),
+            }
+        }
+
+        let mut config = supported.config();
+        if let Some(frames) = buffer_size {
+            let frames = frames.clamp(MIN_BUFFER_SIZE, MAX_BUFFER_SIZE);
+            let frames = match *supported.buffer_size() {
+                SupportedBufferSize::Range { min, max } if !(min..=max).contains(&frames) => {
+                    warn!(min, max, "buffer size {} is outside what the device supports", frames);
+                    frames.clamp(min, max)
+                }
+                _ => frames,
+            };
+            config.buffer_size = BufferSize::Fixed(frames);
+        }
+        Ok(Output {
+            device,
+            config,
+            sample_format: supported.sample_format(),
+            failed: Arc::new(AtomicBool::new(false)),
+            latency: Arc::new(AtomicU32::new(0)),
+        })
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.config.sample_rate.0
+    }
+
+    // How far behind the synth the speakers are from buffering alone; None
+    // if the device picks its own buffer size.
+    pub fn buffer_latency(&self) -> Option<Duration> {
+        match self.config.buffer_size {
+            BufferSize::Fixed(frames) => Some(Duration::from_secs_f64(frames as f64 / self.sample_rate() as f64)),
+            BufferSize::Default => None,
+        }
+    }
+
+    // How long samples take from the synth to the device's output, from the
+    // timestamps the backend gives each buffer, so it includes the driver's
+    // own buffering. None until the stream has played a buffer, or if the
+    // backend doesn't report it.
+    pub fn measured_latency(&self) -> Option<Duration> {
+        match self.latency.load(Ordering::Relaxed) {
+            0 => None,
+            micros => Some(Duration::from_micros(micros as u64)),
+        }
+    }
+
+    // Whether the stream has reported an error, as when the device is
+    // unplugged, and needs opening again.
+    pub fn failed(&self) -> bool {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    // Starts pulling interleaved stereo samples from `source`, locking it
+    // once per buffer. Sound stops when the returned stream is dropped,
+    // leaving the source to play on another output.
+    pub fn play<S>(&self, source: Arc<Mutex<S>>) -> Result<Stream, SynthError>
+    where
+        S: Iterator<Item = f32> + Send + 'static,
+    {
+        let stream = match self.sample_format {
+            SampleFormat::F32 => self.build::<f32, S>(source),
+            SampleFormat::I16 => self.build::<i16, S>(source),
+            SampleFormat::U16 => self.build::<u16, S>(source),
+        };
+        let stream = stream.map_err(StreamError::BuildStreamError)?;
+        stream.play().map_err(StreamError::PlayStreamError)?;
+        Ok(stream)
+    }
+
+    fn build<T: Sample, S: Iterator<Item = f32> + Send + 'static>(
+        &self,
+        source: Arc<Mutex<S>>,
+    ) -> Result<Stream, cpal::BuildStreamError> {
+        let channels = self.config.channels as usize;
+        let failed = Arc::clone(&self.failed);
+        let latency = Arc::clone(&self.latency);
+        self.device.build_output_stream(
+            &self.config,
+            move |data: &mut [T], info: &OutputCallbackInfo| {
+                let timestamp = info.timestamp();
+                if let Some(delay) = timestamp.playback.duration_since(&timestamp.callback) {
+                    latency.store(delay.as_micros().min(u32::MAX as u128) as u32, Ordering::Relaxed);
+                }
+                let mut source = source.lock().unwrap_or_else(PoisonError::into_inner);
+                for frame in data.chunks_mut(channels) {
+                    let left = source.next().unwrap_or(0.0);
+                    let right = source.next().unwrap_or(0.0);
+                    // Mono devices get both sides; extra channels stay silent.
+                    for (channel, sample) in frame.iter_mut().enumerate() {
+                        let value = match (channels, channel) {
+                            (1, _) => (left + right) * 0.5,
+                            (_, 0) => left,
+                            (_, 1) => right,
+                            _ => 0.0,
+                        };
+                        *sample = <T as Sample>::from(&value);
+                    }
+                }
+            },
+            move |e| {
+                error!("audio output stream failed: {}", e);
+                failed.store(true, Ordering::Relaxed);
+            },
+        )
+    }
+}
@@ -0,0 +1,65 @@
+use std::time::{Duration, Instant};
+
+const CLOCKS_PER_BEAT: u32 = 24;
+const TAPS: usize = 4; // Taps averaged for tap tempo
+const TAP_TIMEOUT: Duration = Duration::from_secs(2);
+pub const MIN_TEMPO: f32 = 20.0;
+pub const MAX_TEMPO: f32 = 300.0;
+
+// Follows the tempo of incoming MIDI clock (0xF8), measured once per beat.
+#[derive(Clone, Debug, Default)]
+pub struct MidiClock {
+    beat_start: Option<Instant>,
+    ticks: u32,
+}
+
+impl MidiClock {
+    // Returns the tempo in beats per minute at the end of every beat.
+    pub fn tick(&mut self, now: Instant) -> Option<f32> {
+        let beat_start = match self.beat_start {
+            Some(beat_start) => beat_start,
+            None => {
+                self.beat_start = Some(now);
+                return None;
+            }
+        };
+
+        self.ticks += 1;
+        if self.ticks < CLOCKS_PER_BEAT {
+            return None;
+        }
+        self.ticks = 0;
+        self.beat_start = Some(now);
+        let seconds = now.duration_since(beat_start).as_secs_f32();
+        (seconds > 0.0).then(|| (60.0 / seconds).clamp(MIN_TEMPO, MAX_TEMPO))
+    }
+
+    // Starts measuring afresh, for MIDI Start, Continue and Stop.
+    pub fn reset(&mut self) {
+        self.beat_start = None;
+        self.ticks = 0;
+    }
+}
+
+// Averages the gaps between the last few taps; a pause starts over.
+#[derive(Clone, Debug, Default)]
+pub struct TapTempo {
+    taps: [Option<Instant>; TAPS],
+}
+
+impl TapTempo {
+    pub fn tap(&mut self, now: Instant) -> Option<f32> {
+        if self.taps[TAPS - 1].is_some_and(|last| now.duration_since(last) > TAP_TIMEOUT) {
+            self.taps = [None; TAPS];
+        }
+        self.taps.rotate_left(1);
+        self.taps[TAPS - 1] = Some(now);
+
+        let taps: Vec<Instant> = self.taps.iter().flatten().copied().collect();
+        if taps.len() < 2 {
+            return None;
+        }
+        let seconds = taps[taps.len() - 1].duration_since(taps[0]).as_secs_f32() / (taps.len() - 1) as f32;
+        (seconds > 0.0).then(|| (60.0 / seconds).clamp(MIN_TEMPO, MAX_TEMPO))
+    }
+}
@@ -0,0 +1,99 @@
+use std::f32::consts::TAU;
+
+use serde::{Deserialize, Serialize};
+
+const SILENCE: f32 = -120.0; // dB the detector treats as no signal at all
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompressorSettings {
+    pub threshold: f32, // dBFS
+    pub ratio: f32,     // 4.0 for 4:1
+    pub attack: f32,    // Seconds
+    pub release: f32,   // Seconds
+    pub makeup: f32,    // dB
+    // Hz the detector is high-passed at, so the bass doesn't pump the mix.
+    pub sidechain_highpass: Option<f32>,
+}
+
+impl Default for CompressorSettings {
+    fn default() -> Self {
+        Self {
+            threshold: -18.0,
+            ratio: 4.0,
+            attack: 0.005,
+            release: 0.1,
+            makeup: 0.0,
+            sidechain_highpass: None,
+        }
+    }
+}
+
+// A feed-forward compressor, stereo-linked so the image doesn't shift. The
+// detector follows the louder channel's level in dB, and everything over
+// the threshold is turned down by the ratio.
+pub struct Compressor {
+    settings: CompressorSettings,
+    attack: f32,
+    release: f32,
+    highpass: f32,            // One-pole coefficient, 1.0 for no filter
+    sidechain: [[f32; 2]; 2], // Previous input and output of each channel's high-pass
+    level: f32,               // dB
+}
+
+impl Compressor {
+    pub fn new(settings: &CompressorSettings, sample_rate: u32) -> Compressor {
+        let mut compressor = Compressor {
+            settings: *settings,
+            attack: 0.0,
+            release: 0.0,
+            highpass: 1.0,
+            sidechain: [[0.0; 2]; 2],
+            level: SILENCE,
+        };
+        compressor.set_sample_rate(sample_rate);
+        compressor
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        let coefficient = |time: f32| 1.0 - (-1.0 / (time.max(0.0001) * sample_rate as f32)).exp();
+        self.attack = coefficient(self.settings.attack);
+        self.release = coefficient(self.settings.release);
+        self.highpass = match self.settings.sidechain_highpass {
+            Some(frequency) => {
+                let rc = 1.0 / (TAU * frequency.max(1.0));
+                rc / (rc + 1.0 / sample_rate as f32)
+            }
+            None => 1.0,
+        };
+    }
+
+    // Processes interleaved stereo `buffer` in place.
+    pub fn process(&mut self, buffer: &mut [f32]) {
+        let settings = &self.settings;
+        let slope = 1.0 - 1.0 / settings.ratio.max(1.0);
+        for frame in buffer.chunks_exact_mut(2) {
+            let mut peak: f32 = 0.0;
+            for (sample, sidechain) in frame.iter().zip(self.sidechain.iter_mut()) {
+                let detected = match settings.sidechain_highpass {
+                    Some(_) => {
+                        let [input, output] = sidechain;
+                        *output = self.highpass * (*output + sample - *input);
+                        *input = *sample;
+                        *output
+                    }
+                    None => *sample,
+                };
+                peak = peak.max(detected.abs());
+            }
+
+            let level = (20.0 * peak.log10()).max(SILENCE);
+            let coefficient = if level > self.level { self.attack } else { self.release };
+            self.level += (level - self.level) * coefficient;
+            let reduction = (self.level - settings.threshold).max(0.0) * slope;
+            let gain = 10.0_f32.powf((settings.makeup - reduction) / 20.0);
+            frame[0] *= gain;
+            frame[1] *= gain;
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::SynthError;
+
+// Startup options for live mode, read from the user's config directory
+// (~/.config/wavetable_synth/config.toml on Linux). Command-line flags
+// override anything set here.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub audio_device: Option<String>,
+    pub sample_rate: Option<u32>, // The device's default if unset
+    pub buffer_size: Option<u32>, // Frames; the device's default if unset
+    pub midi_ports: Vec<String>,  // Name fragments; every port if empty
+    pub midi_out: Option<String>,
+    pub preset: Option<PathBuf>,
+    pub bank: Option<PathBuf>,
+    pub performance: Option<PathBuf>,
+    pub wavetable: Option<PathBuf>, // A folder of single-cycle WAVs
+    pub midi_map: PathBuf,           // Next to this file unless set
+    pub master_tune: Option<f32>,    // Cents
+    pub transpose: Option<i32>,      // Keys
+    pub mpe_bend_range: Option<f32>, // Semitones each note's channel bends; MPE is off if unset
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            audio_device: None,
+            sample_rate: None,
+            buffer_size: None,
+            midi_ports: Vec::new(),
+            midi_out: None,
+            preset: None,
+            bank: None,
+            performance: None,
+            wavetable: None,
+            midi_map: Config::dir().map_or_else(|| PathBuf::from("midi_map.json"), |dir| dir.join("midi_map.json")),
+            master_tune: None,
+            transpose: None,
+            mpe_bend_range: None,
+        }
+    }
+}
+
+impl Config {
+    // Where config.toml and the learned MIDI map live.
+    pub fn dir() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("wavetable_synth"))
+    }
+
+    pub fn path() -> Option<PathBuf> {
+        Config::dir().map(|dir| dir.join("config.toml"))
+    }
+
+    // The defaults if there's no config file.
+    pub fn load() -> Result<Config, SynthError> {
+        match Config::path() {
+            Some(path) if path.exists() => Config::load_from(&path),
+            _ => Ok(Config::default()),
+        }
+    }
+
+    pub fn load_from(path: &Path) -> Result<Config, SynthError> {
+        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    // Writes the settings where `load` will find them next time, returning
+    // the path.
+    pub fn save(&self) -> Result<PathBuf, SynthError> {
+        let path = Config::path().ok_or(SynthError::NoConfigDir)?;
+        self.save_to(&path)?;
+        Ok(path)
+    }
+
+    pub fn save_to(&self, path: &Path) -> Result<(), SynthError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
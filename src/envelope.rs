@@ -1,56 +1,140 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum EnvelopeMode {
+    Adsr,
+    Loop,    // Attack and decay repeat while the key is held
+    OneShot, // Attack then decay to silence, ignoring the note-off
+}
+
+#[derive(Clone)]
 pub struct ADSR {
+    mode: EnvelopeMode,
     attack: f32,
     decay: f32,
     sustain: f32,
     release: f32,
     start_time: f32,
     end_time: f32,
+    start_level: f32,   // Where the attack ramps up from
+    release_level: f32, // Where the release ramps down from
+    fade: Option<f32>,  // Seconds, standing in for the release while cutting a note off
 }
 
 impl ADSR {
     pub fn new(attack: f32, decay: f32, sustain: f32, release: f32) -> Self {
         Self {
+            mode: EnvelopeMode::Adsr,
             attack,
             decay,
             sustain,
             release,
             start_time: 0.0,
-            end_time: 0.0,
+            end_time: f32::INFINITY,
+            start_level: 0.0,
+            release_level: 0.0,
+            fade: None,
         }
     }
 
+    pub fn set_mode(&mut self, mode: EnvelopeMode) {
+        self.mode = mode;
+    }
+
+    pub fn set_attack(&mut self, attack: f32) {
+        self.attack = attack.max(0.0);
+    }
+
+    pub fn set_decay(&mut self, decay: f32) {
+        self.decay = decay.max(0.0);
+    }
+
+    pub fn set_sustain(&mut self, sustain: f32) {
+        self.sustain = sustain;
+    }
+
+    pub fn set_release(&mut self, release: f32) {
+        self.release = release.max(0.0);
+    }
+
     pub fn start(&mut self, start_time: f32) {
         self.start_time = start_time;
     }
 
+    // Starts the envelope over at `time`, with the attack rising from the
+    // current level rather than zero if `from_current` is set.
+    pub fn restart(&mut self, time: f32, from_current: bool) {
+        self.start_level = if from_current { self.value(time) } else { 0.0 };
+        self.start_time = time;
+        self.end_time = f32::INFINITY;
+        self.fade = None;
+    }
+
     pub fn stop(&mut self, end_time: f32) {
+        if self.mode == EnvelopeMode::OneShot || self.fade.is_some() {
+            return;
+        }
+        // Release from wherever the envelope is, which may still be in the
+        // attack or decay.
+        self.release_level = self.value(end_time);
         self.end_time = end_time;
     }
 
+    // Releases over `duration` in any mode, for cutting notes off quickly.
+    // The release and mode come back if the envelope restarts.
+    pub fn fade_out(&mut self, time: f32, duration: f32) {
+        self.release_level = self.value(time);
+        self.end_time = time;
+        self.fade = Some(duration);
+    }
+
+    pub fn is_finished(&self, time: f32) -> bool {
+        match self.mode {
+            EnvelopeMode::OneShot if self.fade.is_none() => time >= self.start_time + self.attack + self.decay,
+            _ => time >= self.end_time + self.release_time(),
+        }
+    }
+
+    fn release_time(&self) -> f32 {
+        self.fade.unwrap_or(self.release)
+    }
+
     pub fn value(&self, time: f32) -> f32 {
         if time < self.start_time {
             0.0
-        } else if time < self.start_time + self.attack {
-            (time - self.start_time) / self.attack
-        } else if time < self.start_time + self.attack + self.decay {
-            1.0 + (self.sustain - 1.0) * (time - self.start_time - self.attack) / self.decay
         } else if time < self.end_time {
-            self.sustain
-        } else if time < self.end_time + self.release {
-            self.sustain * (1.0 - (time - self.end_time) / self.release)
+            self.held_value(time)
+        } else if time < self.end_time + self.release_time() {
+            self.release_level * (1.0 - (time - self.end_time) / self.release_time())
         } else {
             0.0
         }
     }
 
-    pub fn clone(&self) -> ADSR {
-        return ADSR {
-            attack: self.attack,
-            decay: self.decay,
-            sustain: self.sustain,
-            release: self.release,
-            start_time: self.start_time,
-            end_time: self.end_time,
+    fn held_value(&self, time: f32) -> f32 {
+        let cycle = self.attack + self.decay;
+        let time = match self.mode {
+            EnvelopeMode::Loop if cycle > 0.0 && time >= self.start_time + cycle => {
+                // Later cycles rise from the sustain level the last one left off at.
+                let cycle_time = (time - self.start_time) % cycle;
+                if cycle_time < self.attack {
+                    return self.sustain + (1.0 - self.sustain) * cycle_time / self.attack;
+                }
+                self.start_time + cycle_time
+            }
+            _ => time,
         };
+        let sustain = match self.mode {
+            EnvelopeMode::OneShot => 0.0,
+            _ => self.sustain,
+        };
+
+        if time < self.start_time + self.attack {
+            self.start_level + (1.0 - self.start_level) * (time - self.start_time) / self.attack
+        } else if time < self.start_time + self.attack + self.decay {
+            1.0 + (sustain - 1.0) * (time - self.start_time - self.attack) / self.decay
+        } else {
+            sustain
+        }
     }
 }
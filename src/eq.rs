@@ -0,0 +1,148 @@
+use std::f32::consts::TAU;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EqBand {
+    pub frequency: f32, // Hz, the corner of a shelf or the centre of the mid band
+    pub gain: f32,      // dB
+    pub q: f32,
+}
+
+// Low shelf, parametric mid and high shelf, all flat by default.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EqSettings {
+    pub low: EqBand,
+    pub mid: EqBand,
+    pub high: EqBand,
+}
+
+impl Default for EqSettings {
+    fn default() -> Self {
+        Self {
+            low: EqBand {
+                frequency: 120.0,
+                gain: 0.0,
+                q: 0.707,
+            },
+            mid: EqBand {
+                frequency: 1000.0,
+                gain: 0.0,
+                q: 1.0,
+            },
+            high: EqBand {
+                frequency: 8000.0,
+                gain: 0.0,
+                q: 0.707,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Shape {
+    LowShelf,
+    Peak,
+    HighShelf,
+}
+
+// One stereo band, in transposed direct form II with the coefficients from
+// the Audio EQ Cookbook, normalised so a0 is 1.
+#[derive(Clone, Copy)]
+struct Biquad {
+    b: [f32; 3],
+    a: [f32; 2],
+    state: [[f32; 2]; 2], // Per channel
+}
+
+impl Biquad {
+    fn new(shape: Shape, band: &EqBand, sample_rate: u32) -> Biquad {
+        let nyquist = sample_rate as f32 * 0.5;
+        let omega = TAU * band.frequency.clamp(10.0, nyquist * 0.95) / sample_rate as f32;
+        let (sin, cos) = omega.sin_cos();
+        let alpha = sin / (2.0 * band.q.max(0.1));
+        let a = 10.0_f32.powf(band.gain.clamp(-24.0, 24.0) / 40.0);
+        let shelf = 2.0 * a.sqrt() * alpha;
+
+        let (b0, b1, b2, a0, a1, a2) = match shape {
+            Shape::LowShelf => (
+                a * ((a + 1.0) - (a - 1.0) * cos + shelf),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                a * ((a + 1.0) - (a - 1.0) * cos - shelf),
+                (a + 1.0) + (a - 1.0) * cos + shelf,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                (a + 1.0) + (a - 1.0) * cos - shelf,
+            ),
+            Shape::Peak => (1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a, 1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a),
+            Shape::HighShelf => (
+                a * ((a + 1.0) + (a - 1.0) * cos + shelf),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                a * ((a + 1.0) + (a - 1.0) * cos - shelf),
+                (a + 1.0) - (a - 1.0) * cos + shelf,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                (a + 1.0) - (a - 1.0) * cos - shelf,
+            ),
+        };
+        Biquad {
+            b: [b0 / a0, b1 / a0, b2 / a0],
+            a: [a1 / a0, a2 / a0],
+            state: [[0.0; 2]; 2],
+        }
+    }
+
+    fn next_sample(&mut self, channel: usize, input: f32) -> f32 {
+        let state = &mut self.state[channel];
+        let output = self.b[0] * input + state[0];
+        state[0] = self.b[1] * input - self.a[0] * output + state[1];
+        state[1] = self.b[2] * input - self.a[1] * output;
+        output
+    }
+}
+
+pub struct Eq {
+    settings: EqSettings,
+    bands: [Biquad; 3],
+    flat: bool, // Every band at 0 dB, so there's nothing to do
+}
+
+impl Eq {
+    pub fn new(settings: &EqSettings, sample_rate: u32) -> Eq {
+        let mut eq = Eq {
+            settings: *settings,
+            bands: [Biquad::new(Shape::Peak, &settings.mid, sample_rate); 3],
+            flat: true,
+        };
+        eq.set_sample_rate(sample_rate);
+        eq
+    }
+
+    // Recalculates the bands, which keep their state so the change is
+    // seamless.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        let settings = &self.settings;
+        let bands = [
+            (Shape::LowShelf, &settings.low),
+            (Shape::Peak, &settings.mid),
+            (Shape::HighShelf, &settings.high),
+        ];
+        for (biquad, (shape, band)) in self.bands.iter_mut().zip(bands) {
+            let state = biquad.state;
+            *biquad = Biquad::new(shape, band, sample_rate);
+            biquad.state = state;
+        }
+        self.flat = bands.iter().all(|(_, band)| band.gain == 0.0);
+    }
+
+    // Processes interleaved stereo `buffer` in place.
+    pub fn process(&mut self, buffer: &mut [f32]) {
+        if self.flat {
+            return;
+        }
+        for frame in buffer.chunks_exact_mut(2) {
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                *sample = self.bands.iter_mut().fold(*sample, |sample, band| band.next_sample(channel, sample));
+            }
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use midir::{ConnectError, InitError, MidiInput, MidiOutput, PortInfoError};
+use rodio::cpal::DefaultStreamConfigError;
+use rodio::{DevicesError, PlayError, StreamError};
+
+#[derive(Debug)]
+pub enum SynthError {
+    MidiInit(InitError),
+    MidiConnect(ConnectError<MidiInput>),
+    MidiPortInfo(PortInfoError),
+    NoMidiInput,
+    MidiOutputConnect(ConnectError<MidiOutput>),
+    NoMidiOutput(String),
+    AudioDevices(DevicesError),
+    AudioConfig(DefaultStreamConfigError),
+    NoAudioDevice(String),
+    AudioStream(StreamError),
+    AudioPlay(PlayError),
+    ChannelClosed(&'static str),
+    Io(io::Error),
+    ConfigFormat(toml::de::Error),
+    ConfigWrite(toml::ser::Error),
+    NoConfigDir,
+    PresetFormat(serde_json::Error),
+    MidiFile(midly::Error),
+    TuningFormat(String),
+    Sysex(String),
+    Wav(hound::Error),
+    NoWavetableFrames(PathBuf),
+}
+
+impl fmt::Display for SynthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SynthError::MidiInit(e) => write!(f, "MIDI initialisation failed: {}", e),
+            SynthError::MidiConnect(e) => write!(f, "MIDI connection failed: {}", e),
+            SynthError::MidiPortInfo(e) => write!(f, "MIDI port query failed: {}", e),
+            SynthError::NoMidiInput => write!(f, "no MIDI input port available"),
+            SynthError::MidiOutputConnect(e) => write!(f, "MIDI output connection failed: {}", e),
+            SynthError::NoMidiOutput(name) => write!(f, "no MIDI output port named {:?}", name),
+            SynthError::AudioDevices(e) => write!(f, "audio device query failed: {}", e),
+            SynthError::AudioConfig(e) => write!(f, "audio device configuration failed: {}", e),
+            SynthError::NoAudioDevice(name) => write!(f, "no audio output device named {:?}", name),
+            SynthError::AudioStream(e) => write!(f, "audio output stream failed: {}", e),
+            SynthError::AudioPlay(e) => write!(f, "audio playback failed: {}", e),
+            SynthError::ChannelClosed(what) => write!(f, "{} channel closed", what),
+            SynthError::Io(e) => write!(f, "I/O error: {}", e),
+            SynthError::ConfigFormat(e) => write!(f, "invalid config file: {}", e),
+            SynthError::ConfigWrite(e) => write!(f, "couldn't write the config file: {}", e),
+            SynthError::NoConfigDir => write!(f, "no config directory to save settings in"),
+            SynthError::PresetFormat(e) => write!(f, "invalid preset: {}", e),
+            SynthError::MidiFile(e) => write!(f, "invalid MIDI file: {}", e),
+            SynthError::TuningFormat(message) => write!(f, "invalid tuning file: {}", message),
+            SynthError::Sysex(message) => write!(f, "invalid sysex message: {}", message),
+            SynthError::Wav(e) => write!(f, "WAV file error: {}", e),
+            SynthError::NoWavetableFrames(dir) => write!(f, "no WAV files in {}", dir.display()),
+        }
+    }
+}
+
+impl Error for SynthError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SynthError::MidiInit(e) => Some(e),
+            SynthError::MidiConnect(e) => Some(e),
+            SynthError::MidiPortInfo(e) => Some(e),
+            SynthError::MidiOutputConnect(e) => Some(e),
+            SynthError::AudioDevices(e) => Some(e),
+            SynthError::AudioConfig(e) => Some(e),
+            SynthError::AudioStream(e) => Some(e),
+            SynthError::AudioPlay(e) => Some(e),
+            SynthError::Io(e) => Some(e),
+            SynthError::ConfigFormat(e) => Some(e),
+            SynthError::ConfigWrite(e) => Some(e),
+            SynthError::PresetFormat(e) => Some(e),
+            SynthError::MidiFile(e) => Some(e),
+            SynthError::Wav(e) => Some(e),
+            SynthError::NoMidiInput
+            | SynthError::NoMidiOutput(_)
+            | SynthError::NoAudioDevice(_)
+            | SynthError::ChannelClosed(_)
+            | SynthError::NoConfigDir
+            | SynthError::TuningFormat(_)
+            | SynthError::Sysex(_)
+            | SynthError::NoWavetableFrames(_) => None,
+        }
+    }
+}
+
+impl From<InitError> for SynthError {
+    fn from(e: InitError) -> Self {
+        SynthError::MidiInit(e)
+    }
+}
+
+impl From<ConnectError<MidiInput>> for SynthError {
+    fn from(e: ConnectError<MidiInput>) -> Self {
+        SynthError::MidiConnect(e)
+    }
+}
+
+impl From<ConnectError<MidiOutput>> for SynthError {
+    fn from(e: ConnectError<MidiOutput>) -> Self {
+        SynthError::MidiOutputConnect(e)
+    }
+}
+
+impl From<PortInfoError> for SynthError {
+    fn from(e: PortInfoError) -> Self {
+        SynthError::MidiPortInfo(e)
+    }
+}
+
+impl From<DevicesError> for SynthError {
+    fn from(e: DevicesError) -> Self {
+        SynthError::AudioDevices(e)
+    }
+}
+
+impl From<DefaultStreamConfigError> for SynthError {
+    fn from(e: DefaultStreamConfigError) -> Self {
+        SynthError::AudioConfig(e)
+    }
+}
+
+impl From<StreamError> for SynthError {
+    fn from(e: StreamError) -> Self {
+        SynthError::AudioStream(e)
+    }
+}
+
+impl From<PlayError> for SynthError {
+    fn from(e: PlayError) -> Self {
+        SynthError::AudioPlay(e)
+    }
+}
+
+impl From<io::Error> for SynthError {
+    fn from(e: io::Error) -> Self {
+        SynthError::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for SynthError {
+    fn from(e: toml::de::Error) -> Self {
+        SynthError::ConfigFormat(e)
+    }
+}
+
+impl From<toml::ser::Error> for SynthError {
+    fn from(e: toml::ser::Error) -> Self {
+        SynthError::ConfigWrite(e)
+    }
+}
+
+impl From<serde_json::Error> for SynthError {
+    fn from(e: serde_json::Error) -> Self {
+        SynthError::PresetFormat(e)
+    }
+}
+
+impl From<midly::Error> for SynthError {
+    fn from(e: midly::Error) -> Self {
+        SynthError::MidiFile(e)
+    }
+}
+
+impl From<hound::Error> for SynthError {
+    fn from(e: hound::Error) -> Self {
+        SynthError::Wav(e)
+    }
+}
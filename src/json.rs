@@ -0,0 +1,20 @@
+use std::fs;
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::error::SynthError;
+
+// Presets, banks, performances and MIDI maps are all kept as JSON files.
+pub fn load_json<T: DeserializeOwned>(path: &Path) -> Result<T, SynthError> {
+    let json = fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&json)?)
+}
+
+// Pretty-printed, for editing by hand.
+pub fn save_json<T: Serialize>(value: &T, path: &Path) -> Result<(), SynthError> {
+    let json = serde_json::to_string_pretty(value)?;
+    fs::write(path, json)?;
+    Ok(())
+}
@@ -0,0 +1,189 @@
+use std::f32::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum LfoShape {
+    Sine,
+    Triangle,
+    Square,
+    SampleAndHold,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum LfoTarget {
+    Pitch,     // Vibrato, depth in semitones
+    Amplitude, // Tremolo, depth from 0.0 to 1.0
+}
+
+impl LfoTarget {
+    // Depth in the target's units that a controller at full travel sets.
+    pub fn max_depth(&self) -> f32 {
+        match self {
+            LfoTarget::Pitch => 12.0,
+            LfoTarget::Amplitude => 1.0,
+        }
+    }
+}
+
+// Note lengths an LFO cycle can lock to, at the preset's tempo.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum LfoSync {
+    Whole,
+    Half,
+    Quarter,
+    QuarterTriplet,
+    Eighth,
+    EighthTriplet,
+    DottedEighth,
+    Sixteenth,
+    SixteenthTriplet,
+}
+
+impl LfoSync {
+    pub fn beats(&self) -> f32 {
+        match self {
+            LfoSync::Whole => 4.0,
+            LfoSync::Half => 2.0,
+            LfoSync::Quarter => 1.0,
+            LfoSync::QuarterTriplet => 2.0 / 3.0,
+            LfoSync::Eighth => 0.5,
+            LfoSync::EighthTriplet => 1.0 / 3.0,
+            LfoSync::DottedEighth => 0.75,
+            LfoSync::Sixteenth => 0.25,
+            LfoSync::SixteenthTriplet => 1.0 / 6.0,
+        }
+    }
+
+    // Cycles per second at `tempo` beats per minute.
+    pub fn rate(&self, tempo: f32) -> f32 {
+        tempo / 60.0 / self.beats()
+    }
+}
+
+#[derive(Clone)]
+pub struct LFO {
+    sample_rate: u32,
+    shape: LfoShape,
+    rate: f32,
+    depth: f32,
+    target: LfoTarget,
+    phase: f32,
+    held_value: f32,
+    seed: u32,
+    retrigger: bool,
+    fade_in: f32,
+    samples: u64,
+}
+
+impl LFO {
+    pub fn new(sample_rate: u32, shape: LfoShape, rate: f32, depth: f32, target: LfoTarget) -> Self {
+        Self {
+            sample_rate,
+            shape,
+            rate,
+            depth,
+            target,
+            phase: 0.0,
+            held_value: 0.0,
+            seed: 0x2545_f491,
+            retrigger: true,
+            fade_in: 0.0,
+            samples: 0,
+        }
+    }
+
+    // Keeps a fade-in as far along in seconds.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.samples = (self.samples as f64 * sample_rate as f64 / self.sample_rate as f64) as u64;
+        self.sample_rate = sample_rate;
+    }
+
+    pub fn set_shape(&mut self, shape: LfoShape) {
+        self.shape = shape;
+    }
+
+    pub fn set_rate(&mut self, rate: f32) {
+        self.rate = rate;
+    }
+
+    pub fn set_depth(&mut self, depth: f32) {
+        self.depth = depth;
+    }
+
+    pub fn set_target(&mut self, target: LfoTarget) {
+        self.target = target;
+    }
+
+    // Ramps the LFO in over `fade_in` seconds from the start of a note.
+    pub fn set_fade_in(&mut self, fade_in: f32) {
+        self.fade_in = fade_in.max(0.0);
+    }
+
+    // Off leaves the LFO free-running across notes instead of restarting its
+    // cycle on each one.
+    pub fn set_retrigger(&mut self, retrigger: bool) {
+        self.retrigger = retrigger;
+    }
+
+    // Starts the LFO for a note beginning `time` seconds into the session,
+    // lining a free-running LFO up with one that has run since time zero.
+    pub fn start(&mut self, time: f64) {
+        self.phase = if self.retrigger {
+            0.0
+        } else {
+            (time * self.rate as f64).fract() as f32
+        };
+        self.samples = 0;
+    }
+
+    pub fn target(&self) -> LfoTarget {
+        self.target
+    }
+
+    pub fn depth(&self) -> f32 {
+        self.depth
+    }
+
+    // Advances the LFO by one sample and returns its raw value in -1.0..=1.0.
+    pub fn next_value(&mut self) -> f32 {
+        let value = match self.shape {
+            LfoShape::Sine => (2.0 * PI * self.phase).sin(),
+            LfoShape::Triangle => 1.0 - 4.0 * (self.phase - 0.5).abs(),
+            LfoShape::Square => {
+                if self.phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            LfoShape::SampleAndHold => self.held_value,
+        };
+
+        self.phase += self.rate / self.sample_rate as f32;
+        if self.phase >= 1.0 {
+            self.phase -= 1.0;
+            self.held_value = random(&mut self.seed);
+        }
+
+        let fade_samples = self.fade_in * self.sample_rate as f32;
+        self.samples += 1;
+        if (self.samples as f32) < fade_samples {
+            value * self.samples as f32 / fade_samples
+        } else {
+            value
+        }
+    }
+}
+
+// Returns a value in -1.0..=1.0. xorshift32 is plenty for modulation and
+// phase scattering, and keeps results reproducible for a given seed.
+pub(crate) fn random(seed: &mut u32) -> f32 {
+    if *seed == 0 {
+        *seed = 0x2545_f491;
+    }
+    *seed ^= *seed << 13;
+    *seed ^= *seed >> 17;
+    *seed ^= *seed << 5;
+    *seed as f32 / u32::MAX as f32 * 2.0 - 1.0
+}
@@ -0,0 +1,26 @@
+pub mod arp;
+pub mod audio;
+pub mod clock;
+pub mod compressor;
+pub mod config;
+pub mod envelope;
+pub mod eq;
+pub mod error;
+pub mod json;
+pub mod lfo;
+pub mod master;
+pub mod midi;
+pub mod midi_input;
+pub mod midi_map;
+pub mod mod_matrix;
+pub mod params;
+pub mod performance;
+pub mod pluck;
+pub mod preset;
+pub mod render;
+pub mod sampler;
+pub mod synth;
+pub mod sysex;
+pub mod tuning;
+pub mod wavetable;
+pub mod wavetable_oscillator;
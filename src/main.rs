@@ -1,62 +1,847 @@
-use midir::MidiInput;
-use rodio::source::SineWave;
-use rodio::Source;
-use std::error::Error;
-use std::sync::{Arc, Mutex};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use rodio::buffer::SamplesBuffer;
+use rodio::{Sink, Source};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex, PoisonError};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{debug, error, info, trace, warn};
+use tracing_subscriber::EnvFilter;
+use wavetable_synth::audio;
+use wavetable_synth::clock::{MidiClock, TapTempo};
+use wavetable_synth::config::Config;
+use wavetable_synth::error::SynthError;
+use wavetable_synth::midi::pitch_bend;
+use wavetable_synth::midi_input::{self, MidiInputs};
+use wavetable_synth::midi_map::{
+    MidiMap, ALL_NOTES_OFF, ALL_SOUND_OFF, HOLD, MOD_WHEEL, SOFT_PEDAL, SOSTENUTO, SUSTAIN, TIMBRE,
+};
+use wavetable_synth::params::Parameter;
+use wavetable_synth::performance::{LayeredSynth, Performance};
+use wavetable_synth::preset::{Preset, PresetBank};
+use wavetable_synth::render::{self, BitDepth};
+use wavetable_synth::sampler::Sample;
+use wavetable_synth::synth::{self, SynthEvent, SynthSource};
+use wavetable_synth::sysex::{self, SysexMessage};
+use wavetable_synth::tuning::Tuning;
+use wavetable_synth::wavetable::Wavetable;
 
-fn calculate_frequency(key: u8) -> f32 {
-    let a4 = 440.0;
-    let a4_key = 69;
-    let key_diff = key as i32 - a4_key as i32;
-    let frequency = a4 * 2.0_f32.powf(key_diff as f32 / 12.0);
-    frequency
+const SAMPLE_RATE: u32 = 44100;
+const MIDI_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const CPU_LOAD_WARNING: f32 = 80.0; // Percent
+const DC_OFFSET_WARNING: f32 = 0.01; // Linear, of full scale
+
+// With no subcommand, plays live from MIDI input.
+#[derive(Parser)]
+#[command(name = "wavetable_synth", about = "A wavetable synthesiser", args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    live: LiveArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "Render a MIDI file to WAV without touching any audio or MIDI devices")]
+    Render(RenderArgs),
+    #[command(about = "Render a MIDI file and play it through the output device")]
+    Play(PlayArgs),
+    #[command(about = "List and search a preset bank, or build one from a directory")]
+    Bank(BankArgs),
+    #[command(about = "Convert between preset files and sysex dumps")]
+    Sysex(SysexArgs),
+    #[command(about = "Write a random preset, or a mutation of an existing one")]
+    Randomize(RandomizeArgs),
+    #[command(about = "List the parameters the MIDI map can bind, with their ranges and defaults")]
+    Params,
+}
+
+#[derive(Args)]
+struct LiveArgs {
+    #[arg(long, value_name = "PRESET.JSON", help = "Preset to start with, instead of the configured one")]
+    preset: Option<PathBuf>,
+    #[arg(long, help = "Preset bank to switch between with program changes")]
+    bank: Option<PathBuf>,
+    #[arg(long, value_name = "PERFORMANCE.JSON", help = "Two presets to layer or split, instead of a single preset")]
+    performance: Option<PathBuf>,
+    #[command(flatten)]
+    wavetable: WavetableArgs,
+    #[arg(long, help = "Audio output device")]
+    device: Option<String>,
+    #[arg(long, help = "Sample rate to run the device at, if it supports it")]
+    sample_rate: Option<u32>,
+    #[arg(
+        long,
+        value_name = "FRAMES",
+        value_parser = clap::value_parser!(u32).range(audio::MIN_BUFFER_SIZE as i64..=audio::MAX_BUFFER_SIZE as i64),
+        help = "Audio buffer size, trading latency against dropouts"
+    )]
+    buffer_size: Option<u32>,
+    #[arg(long, help = "List audio output devices and exit")]
+    list_devices: bool,
+    #[arg(long, help = "Save the audio device, buffer size and other options given here to config.toml")]
+    save_config: bool,
+    #[arg(long = "midi-port", value_name = "NAME", help = "MIDI input ports to listen on, by part of their name; all if none")]
+    midi_ports: Vec<String>,
+    #[arg(long, value_name = "NAME", help = "MIDI output port for answering sysex dump requests")]
+    midi_out: Option<String>,
+    #[arg(long, help = "List MIDI input ports and exit")]
+    list_midi_ports: bool,
+    #[arg(
+        long,
+        value_name = "SEMITONES",
+        num_args = 0..=1,
+        default_missing_value = "48",
+        help = "Play MPE, with each note on its own channel bending this far; 48 if not given"
+    )]
+    mpe: Option<f32>,
+    #[command(flatten)]
+    sample: SampleArgs,
+    #[command(flatten)]
+    tuning: TuningArgs,
+}
+
+#[derive(Args)]
+struct RenderArgs {
+    #[arg(value_name = "SONG.MID")]
+    midi_path: PathBuf,
+    #[arg(long, value_name = "SONG.WAV")]
+    out: PathBuf,
+    #[arg(long)]
+    preset: Option<PathBuf>,
+    #[command(flatten)]
+    wavetable: WavetableArgs,
+    #[arg(long, default_value_t = SAMPLE_RATE)]
+    sample_rate: u32,
+    #[arg(long, value_enum, default_value = "16")]
+    bit_depth: BitDepthArg,
+    #[arg(long, help = "Add TPDF dither to 16-bit output")]
+    dither: bool,
+    #[arg(long, help = "Scale the render so its peak is just under full scale")]
+    normalize: bool,
+    #[arg(long, help = "Trim silence from the start and end")]
+    trim: bool,
+    #[command(flatten)]
+    sample: SampleArgs,
+    #[command(flatten)]
+    tuning: TuningArgs,
+}
+
+#[derive(Args)]
+struct PlayArgs {
+    #[arg(value_name = "SONG.MID")]
+    midi_path: PathBuf,
+    #[arg(long)]
+    preset: Option<PathBuf>,
+    #[command(flatten)]
+    wavetable: WavetableArgs,
+    #[arg(long, help = "Audio output device")]
+    device: Option<String>,
+    #[arg(long = "loop", help = "Play until stopped with Ctrl-C")]
+    looping: bool,
+    #[command(flatten)]
+    sample: SampleArgs,
+    #[command(flatten)]
+    tuning: TuningArgs,
+}
+
+#[derive(Args)]
+struct BankArgs {
+    #[arg(value_name = "BANK.JSON", required_unless_present = "build")]
+    bank: Option<PathBuf>,
+    #[arg(long, value_name = "PRESET_DIR", requires = "out", help = "Gather a directory of presets into a bank")]
+    build: Option<PathBuf>,
+    #[arg(long, value_name = "BANK.JSON")]
+    out: Option<PathBuf>,
+    #[arg(long)]
+    category: Option<String>,
+    #[arg(long, default_value = "", help = "Text to find in the name, author or tags")]
+    search: String,
+}
+
+#[derive(Args)]
+struct SysexArgs {
+    #[arg(value_name = "IN", help = "A preset .json to encode, or a .syx dump to decode")]
+    in_path: PathBuf,
+    #[arg(long)]
+    out: PathBuf,
+}
+
+#[derive(Args)]
+struct RandomizeArgs {
+    #[arg(long, value_name = "PRESET.JSON")]
+    out: PathBuf,
+    #[arg(long, value_name = "PRESET.JSON", help = "Preset to mutate, instead of the init patch")]
+    from: Option<PathBuf>,
+    #[arg(long, help = "Seed for the dice; the clock if not given")]
+    seed: Option<u32>,
+    #[arg(long, default_value_t = 1.0, help = "How far to move each parameter, 0.0 to 1.0")]
+    amount: f32,
+}
+
+// Live, render and play modes accept this, falling back to the built-in
+// shapes.
+#[derive(Args)]
+struct WavetableArgs {
+    #[arg(long, value_name = "DIR", help = "Folder of single-cycle WAVs to use as the wavetable, one frame per file")]
+    wavetable: Option<PathBuf>,
+}
+
+// Live, render and play modes accept these.
+#[derive(Args)]
+struct SampleArgs {
+    #[arg(long, value_name = "FILE.WAV", help = "Recording to layer under the wavetable, pitched across the keyboard")]
+    sample: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "KEY",
+        default_value_t = 60,
+        value_parser = clap::value_parser!(u8).range(0..=127),
+        help = "MIDI key the sample sounds at its own pitch on"
+    )]
+    sample_root: u8,
+    #[arg(
+        long,
+        num_args = 2,
+        value_names = ["START", "END"],
+        help = "Frames to loop between while the note sounds; plays once if not given"
+    )]
+    sample_loop: Vec<usize>,
+}
+
+// Every mode accepts these. A temperament is applied first, then the
+// scale and mapping files, then the adjustments.
+#[derive(Args)]
+struct TuningArgs {
+    #[arg(long, value_enum, help = "Built-in tuning to start from")]
+    temperament: Option<Temperament>,
+    #[arg(long, value_name = "SCALE.SCL", help = "Scala scale file")]
+    scl: Option<PathBuf>,
+    #[arg(long, value_name = "MAP.KBM", help = "Scala keyboard mapping file")]
+    kbm: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "HZ",
+        value_parser = parse_frequency,
+        help = "Frequency of the reference key, A4 unless mapped otherwise"
+    )]
+    reference_pitch: Option<f32>,
+    #[arg(long, value_name = "CENTS", allow_negative_numbers = true, help = "Detune everything, up to 100 cents either way")]
+    master_tune: Option<f32>,
+    #[arg(long, value_name = "SEMITONES", allow_negative_numbers = true, help = "Shift every note, up to 24 keys either way")]
+    transpose: Option<i32>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Temperament {
+    Equal,
+    Just,
+    QuarterTone,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum BitDepthArg {
+    #[value(name = "16")]
+    Int16,
+    #[value(name = "24")]
+    Int24,
+    #[value(name = "32")]
+    Float32,
+}
+
+impl TuningArgs {
+    fn tuning(&self) -> Result<Tuning, SynthError> {
+        let mut tuning = match self.temperament {
+            Some(Temperament::Equal) | None => Tuning::default(),
+            Some(Temperament::Just) => Tuning::just_intonation(),
+            Some(Temperament::QuarterTone) => Tuning::quarter_tone(),
+        };
+        if let Some(path) = &self.scl {
+            tuning.load_scale(path)?;
+        }
+        if let Some(path) = &self.kbm {
+            tuning.load_mapping(path)?;
+        }
+        if let Some(frequency) = self.reference_pitch {
+            tuning.set_reference_frequency(frequency)?;
+        }
+        if let Some(cents) = self.master_tune {
+            tuning.set_master_tune(cents);
+        }
+        if let Some(keys) = self.transpose {
+            tuning.set_transpose(keys);
+        }
+        Ok(tuning)
+    }
+}
+
+fn parse_frequency(text: &str) -> Result<f32, String> {
+    match text.parse::<f32>() {
+        Ok(frequency) if frequency.is_finite() && frequency > 0.0 => Ok(frequency),
+        _ => Err(String::from("expected a frequency above 0 Hz")),
+    }
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let (_stream, stream_handle) = rodio::OutputStream::try_default()?;
-    let frequencies: Arc<Mutex<Vec<f32>>> = Arc::new(Mutex::new(Vec::new()));
+impl SampleArgs {
+    fn sample(&self) -> Result<Option<Arc<Sample>>, SynthError> {
+        let Some(path) = &self.sample else {
+            return Ok(None);
+        };
+        let loop_points = match self.sample_loop[..] {
+            [start, end] => Some((start, end)),
+            _ => None,
+        };
+        Ok(Some(Arc::new(Sample::load(path, self.sample_root, loop_points)?)))
+    }
+}
 
-    let midi_in = MidiInput::new("midir reading input")?;
-    let in_ports = midi_in.ports();
+fn load_preset(path: Option<&Path>) -> Result<Preset, SynthError> {
+    match path {
+        Some(path) => Preset::load(path),
+        None => Ok(Preset::default()),
+    }
+}
 
-    let in_port = match in_ports.first() {
-        Some(port) => port,
-        None => return Err("no input port available".into()),
+// The built-in shapes unless a folder of WAVs is given.
+fn load_wavetable(dir: Option<&Path>) -> Result<Arc<Wavetable>, SynthError> {
+    let wavetable = match dir {
+        Some(dir) => Wavetable::load_dir(dir)?,
+        None => Wavetable::basic_shapes(),
     };
+    Ok(Arc::new(wavetable))
+}
 
-    println!("Listening on: {}", midi_in.port_name(in_port)?);
+// Renders a MIDI file up front and plays it through the output device, for
+// auditioning presets without a controller. Ctrl-C stops a loop.
+fn play_file(args: PlayArgs) -> Result<(), SynthError> {
+    let preset = load_preset(args.preset.as_deref())?;
+    let wavetable = load_wavetable(args.wavetable.wavetable.as_deref())?;
+    let sample = args.sample.sample()?;
+    let tuning = args.tuning.tuning()?;
 
-    let frequencies_clone = Arc::clone(&frequencies);
-    let _conn_in = midi_in.connect(in_port, "midir-read-input", move |_, message, _| {
-        match message {
-            [0x90, key, ..] => { // Note On event
-                let frequency = calculate_frequency(*key);
-                frequencies_clone.lock().unwrap().push(frequency);
-            },
-            _ => (),
+    let (_stream, stream_handle, sample_rate) = audio::open_output(args.device.as_deref())?;
+    let events = render::read_midi_file(&args.midi_path)?;
+    let samples = render::render_events(&events, &preset, &tuning, wavetable, sample, sample_rate);
+    info!("Playing {} with preset {}", args.midi_path.display(), preset.name);
+
+    let sink = Sink::try_new(&stream_handle)?;
+    let song = SamplesBuffer::new(2, sample_rate, samples);
+    if args.looping {
+        sink.append(song.repeat_infinite());
+    } else {
+        sink.append(song);
+    }
+    sink.sleep_until_end();
+    Ok(())
+}
+
+// Lists a bank's presets by program number, optionally filtered, or builds
+// a bank from a directory of preset files.
+fn bank(args: BankArgs) -> Result<(), SynthError> {
+    if let (Some(build_dir), Some(out_path)) = (&args.build, &args.out) {
+        let bank = PresetBank::from_dir(build_dir)?;
+        bank.save(out_path)?;
+        info!("Wrote {} presets to {}", bank.presets.len(), out_path.display());
+        return Ok(());
+    }
+
+    let Some(bank_path) = &args.bank else {
+        return Ok(());
+    };
+    let bank = PresetBank::load(bank_path)?;
+    println!("{} ({})", bank.name, bank.categories().join(", "));
+    for (program, preset) in bank.search(args.category.as_deref(), &args.search) {
+        println!("{:3}  {:24} {:12} {}", program, preset.name, preset.category, preset.tags.join(" "));
+    }
+    Ok(())
+}
+
+// Converts between preset files and sysex dumps, for librarians that send
+// and store .syx files.
+fn convert_sysex(args: SysexArgs) -> Result<(), SynthError> {
+    if args.in_path.extension().is_some_and(|extension| extension == "syx") {
+        match sysex::decode(&fs::read(&args.in_path)?)? {
+            Some(SysexMessage::PresetDump(preset)) => preset.save(&args.out)?,
+            _ => return Err(SynthError::Sysex(String::from("not a preset dump"))),
+        }
+    } else {
+        fs::write(&args.out, sysex::encode_preset(&Preset::load(&args.in_path)?)?)?;
+    }
+    info!("Wrote {}", args.out.display());
+    Ok(())
+}
+
+// Writes a random patch, or with --from and a small --amount, a mutation of
+// an existing one.
+fn randomize(args: RandomizeArgs) -> Result<(), SynthError> {
+    let mut preset = load_preset(args.from.as_deref())?;
+
+    // Without a seed, use the clock so every run rolls something new.
+    let seed = args.seed.unwrap_or_else(|| {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(1, |time| time.subsec_nanos())
+    });
+    preset.randomize(seed, args.amount);
+    preset.name = format!("Random {}", seed);
+    preset.save(&args.out)?;
+    info!(seed, amount = args.amount, "Wrote {}", args.out.display());
+    Ok(())
+}
+
+fn list_parameters() {
+    for parameter in Parameter::ALL {
+        let (min, max) = parameter.range();
+        println!(
+            "{:<20} {:>10} to {:<10} default {:<10} {}",
+            format!("{:?}", parameter),
+            parameter.display(min),
+            parameter.display(max),
+            parameter.display(parameter.default()),
+            parameter.description()
+        );
+    }
+}
+
+// Renders a MIDI file to WAV without touching any audio or MIDI devices.
+fn render(args: RenderArgs) -> Result<(), SynthError> {
+    let preset = load_preset(args.preset.as_deref())?;
+    let wavetable = load_wavetable(args.wavetable.wavetable.as_deref())?;
+    let sample = args.sample.sample()?;
+    let tuning = args.tuning.tuning()?;
+    let bit_depth = match args.bit_depth {
+        BitDepthArg::Int16 => BitDepth::Int16,
+        BitDepthArg::Int24 => BitDepth::Int24,
+        BitDepthArg::Float32 => BitDepth::Float32,
+    };
+
+    let events = render::read_midi_file(&args.midi_path)?;
+    info!("Rendering {} MIDI events from {} with preset {}", events.len(), args.midi_path.display(), preset.name);
+    let mut samples = render::render_events(&events, &preset, &tuning, wavetable, sample, args.sample_rate);
+    if args.trim {
+        render::trim_silence(&mut samples);
+    }
+    if args.normalize {
+        render::normalize(&mut samples);
+    }
+    render::write_wav(&args.out, &samples, args.sample_rate, bit_depth, args.dither)?;
+    info!("Wrote {:.1} s to {}", samples.len() as f32 / 2.0 / args.sample_rate as f32, args.out.display());
+    Ok(())
+}
+
+fn main() -> Result<(), SynthError> {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .init();
+
+    let cli = Cli::parse();
+    match cli.command {
+        Some(Command::Render(args)) => render(args),
+        Some(Command::Play(args)) => play_file(args),
+        Some(Command::Bank(args)) => bank(args),
+        Some(Command::Sysex(args)) => convert_sysex(args),
+        Some(Command::Randomize(args)) => randomize(args),
+        Some(Command::Params) => {
+            list_parameters();
+            Ok(())
+        }
+        None => live(cli.live),
+    }
+}
+
+// Plays from MIDI input until killed.
+fn live(args: LiveArgs) -> Result<(), SynthError> {
+    if args.list_devices {
+        for name in audio::output_devices()? {
+            println!("{}", name);
         }
-    }, ())?;
+        return Ok(());
+    }
+    if args.list_midi_ports {
+        for name in midi_input::input_ports()? {
+            println!("{}", name);
+        }
+        return Ok(());
+    }
 
-    let frequencies_clone = Arc::clone(&frequencies);
-    let handle = thread::spawn(move || {
-        loop {
-            let mut frequencies = frequencies_clone.lock().unwrap();
-            if let Some(frequency) = frequencies.pop() {
-                let source = SineWave::new(frequency as u32);
-                let source_with_duration = source.take_duration(Duration::from_secs_f32(0.5));
-                if let Err(e) = stream_handle.play_raw(source_with_duration.convert_samples()) {
-                    eprintln!("Error playing frequency: {}", e);
+    // Flags override the config file. Ports on the command line replace the
+    // configured ones rather than adding to them.
+    let mut config = Config::load()?;
+    config.preset = args.preset.or(config.preset);
+    config.bank = args.bank.or(config.bank);
+    config.performance = args.performance.or(config.performance);
+    config.wavetable = args.wavetable.wavetable.or(config.wavetable);
+    config.audio_device = args.device.or(config.audio_device);
+    config.sample_rate = args.sample_rate.or(config.sample_rate);
+    config.buffer_size = args.buffer_size.or(config.buffer_size);
+    config.midi_out = args.midi_out.or(config.midi_out);
+    config.master_tune = args.tuning.master_tune.or(config.master_tune);
+    config.transpose = args.tuning.transpose.or(config.transpose);
+    config.mpe_bend_range = args.mpe.or(config.mpe_bend_range);
+    if !args.midi_ports.is_empty() {
+        config.midi_ports = args.midi_ports;
+    }
+    if args.save_config {
+        let path = config.save()?;
+        info!("Saved settings to {}", path.display());
+    }
+    let mut tuning = args.tuning.tuning()?;
+    tuning.set_master_tune(config.master_tune.unwrap_or(0.0));
+    tuning.set_transpose(config.transpose.unwrap_or(0));
+
+    let bank = match &config.bank {
+        Some(path) => PresetBank::load(path)?,
+        None => PresetBank::default(),
+    };
+    // Without a preset of its own, start on the bank's first program.
+    let preset = match &config.preset {
+        Some(path) => Preset::load(path)?,
+        None => bank.presets.first().cloned().unwrap_or_default(),
+    };
+    let performance = match &config.performance {
+        Some(path) => {
+            let performance = Performance::load(path)?;
+            let layers = performance.layers.len();
+            info!(layers, "Using performance: {} ({:?})", performance.name, performance.mode);
+            performance
+        }
+        None => {
+            info!("Using preset: {}", preset.name);
+            Performance::from_preset(preset)
+        }
+    };
+    let wavetable = load_wavetable(config.wavetable.as_deref())?;
+    info!("Wavetable has {} frames", wavetable.frame_count());
+    let sample = args.sample.sample()?;
+
+    // Shared with the terminal, which starts MIDI learn.
+    let midi_map = if config.midi_map.exists() {
+        MidiMap::load(&config.midi_map)?
+    } else {
+        MidiMap::default()
+    };
+    let midi_map = Arc::new(Mutex::new(midi_map));
+
+    let device_name = config.audio_device.as_deref();
+    let mut output = audio::Output::open(device_name, config.sample_rate, config.buffer_size)?;
+    let sample_rate = output.sample_rate();
+    info!(sample_rate, "Audio output open on {}", device_name.unwrap_or("the default device"));
+    match output.buffer_latency() {
+        Some(latency) => info!("Buffer latency {:.1} ms", latency.as_secs_f64() * 1000.0),
+        None => info!("Using the device's default buffer size"),
+    }
+    // MIDI events go to the synth over a channel, so the MIDI thread never
+    // waits on the audio thread; the synth picks them up between blocks.
+    let (event_sender, event_receiver) = mpsc::channel::<SynthEvent>();
+    // The handler and the terminal keep a copy of each layer's patch between
+    // them, following every change they send, to answer sysex dump requests
+    // with. Patch changes go to the selected layer.
+    let layer_presets: Vec<Preset> = performance.layers.iter().map(|layer| layer.preset.clone()).collect();
+    let layer_count = layer_presets.len();
+    let layer_presets = Arc::new(Mutex::new(layer_presets));
+    let selected_layer = Arc::new(AtomicUsize::new(0));
+    let routing = performance.clone();
+    let mut synth = LayeredSynth::new(performance, wavetable, sample, sample_rate);
+    if let Some(bend_range) = config.mpe_bend_range {
+        info!(bend_range, "MPE on");
+        synth.handle(SynthEvent::Mpe(Some(bend_range)));
+    }
+    let source = SynthSource::new(synth, event_receiver);
+    let cpu_load = source.cpu_load();
+    let master_meter = source.master_meter();
+    let source = Arc::new(Mutex::new(source));
+    let mut _stream = Some(output.play(Arc::clone(&source))?);
+
+    // Each Enter on the terminal is a tap-tempo tap, "p" then Enter is the
+    // panic button, a layer number then Enter selects that layer, and
+    // "learn" and a parameter name binds it to the next controller moved.
+    // Anything else is turned away with a reminder of these.
+    let terminal_sender = event_sender.clone();
+    let terminal_layer = Arc::clone(&selected_layer);
+    let terminal_map = Arc::clone(&midi_map);
+    let terminal_presets = Arc::clone(&layer_presets);
+    thread::spawn(move || {
+        let mut tap_tempo = TapTempo::default();
+        for line in io::stdin().lines() {
+            let line = line.unwrap_or_default();
+            let line = line.trim();
+            if let Some(name) = line.strip_prefix("learn").filter(|name| name.is_empty() || name.starts_with(' ')) {
+                let name = name.trim();
+                if name.is_empty() {
+                    warn!("learn needs a parameter name; the params subcommand lists them");
+                    continue;
+                }
+                let parameter = Parameter::ALL
+                    .into_iter()
+                    .find(|parameter| format!("{:?}", parameter).eq_ignore_ascii_case(name));
+                match parameter {
+                    Some(parameter) => {
+                        info!("Move a controller to bind it to {}", parameter.name());
+                        terminal_map.lock().unwrap_or_else(PoisonError::into_inner).learn(parameter);
+                    }
+                    None => warn!("no parameter named {:?}; the params subcommand lists them", name),
+                }
+                continue;
+            }
+            if line == "p" {
+                info!("panic: all sound off");
+                if terminal_sender.send(SynthEvent::AllSoundOff).is_err() {
+                    break;
+                }
+                continue;
+            }
+            if let Ok(layer) = line.parse::<usize>() {
+                if !(1..=layer_count).contains(&layer) {
+                    warn!("no layer {}; there are {}", layer, layer_count);
+                    continue;
+                }
+                info!(layer, "layer selected");
+                terminal_layer.store(layer - 1, Ordering::Relaxed);
+                if terminal_sender.send(SynthEvent::SelectLayer(layer - 1)).is_err() {
+                    break;
+                }
+                continue;
+            }
+            if !line.is_empty() {
+                warn!("unknown command {:?}; try Enter to tap, p, a layer number or learn <Parameter>", line);
+                continue;
+            }
+            if let Some(tempo) = tap_tempo.tap(Instant::now()) {
+                info!(tempo, "tap tempo");
+                for preset in terminal_presets.lock().unwrap_or_else(PoisonError::into_inner).iter_mut() {
+                    Parameter::Tempo.set(preset, tempo);
+                }
+                if terminal_sender.send(SynthEvent::Control(Parameter::Tempo, tempo)).is_err() {
+                    break;
                 }
-                thread::sleep(Duration::from_secs_f32(0.5)); // Adjust the delay as needed
             }
         }
     });
 
-    loop {
-        thread::sleep(Duration::from_secs(1)); // Keep the main thread alive
+    let mut midi_out = config.midi_out.as_deref().map(midi_input::connect_output).transpose()?;
+    let midi_map_path = config.midi_map.clone();
+    let mut midi_clock = MidiClock::default();
+    let mut clock_tempo = 0.0;
+    let handler = move |message: &[u8]| {
+        let send = |event| {
+            if event_sender.send(event).is_err() {
+                error!("{}", SynthError::ChannelClosed("synth"));
+            }
+        };
+        // Channel messages carry their channel, from zero, in the low nibble of
+        // the status byte.
+        let (status, channel) = match message.first() {
+            Some(&status) if status < 0xF0 => (status & 0xF0, status & 0x0F),
+            Some(&status) => (status, 0),
+            None => return,
+        };
+        let send_on = |event| send(SynthEvent::OnChannel(channel, Box::new(event)));
+        let mut layer_presets = layer_presets.lock().unwrap_or_else(PoisonError::into_inner);
+        // The layer a patch change on this channel reaches, to keep its copy.
+        let layer = routing.patch_layer(selected_layer.load(Ordering::Relaxed), channel);
+        match (status, &message[1..]) {
+            (0x90, [key, velocity]) if *velocity > 0 => { // Note On event
+                let Some(frequency) = tuning.frequency(*key) else {
+                    trace!(key, "key not in tuning");
+                    return;
+                };
+                debug!(channel, key, velocity, frequency, "note on");
+                send(SynthEvent::NoteOn { channel, key: *key, frequency, velocity: *velocity });
+            },
+            (0x80, [key, _]) | (0x90, [key, 0]) => { // Note Off event
+                debug!(channel, key, "note off");
+                send(SynthEvent::NoteOff { channel, key: *key });
+            },
+            (0xB0, [cc, value]) => { // Control Change event
+                match *cc {
+                    MOD_WHEEL => send_on(SynthEvent::ModWheel(*value as f32 / 127.0)),
+                    TIMBRE => send(SynthEvent::Timbre { channel, timbre: *value as f32 / 127.0 }),
+                    SUSTAIN => {
+                        debug!(down = *value >= 64, "sustain");
+                        send_on(SynthEvent::Sustain(*value >= 64));
+                    }
+                    SOSTENUTO => {
+                        debug!(down = *value >= 64, "sostenuto");
+                        send_on(SynthEvent::Sostenuto(*value >= 64));
+                    }
+                    SOFT_PEDAL => {
+                        debug!(down = *value >= 64, "soft pedal");
+                        send_on(SynthEvent::SoftPedal(*value >= 64));
+                    }
+                    HOLD => {
+                        debug!(latch = *value >= 64, "hold");
+                        send_on(SynthEvent::Latch(*value >= 64));
+                    }
+                    ALL_SOUND_OFF => {
+                        debug!("all sound off");
+                        send_on(SynthEvent::AllSoundOff);
+                    }
+                    ALL_NOTES_OFF => {
+                        debug!("all notes off");
+                        send_on(SynthEvent::AllNotesOff);
+                    }
+                    _ => (),
+                }
+                let mut midi_map = midi_map.lock().unwrap_or_else(PoisonError::into_inner);
+                let learning = midi_map.learning();
+                if let Some((parameter, value)) = midi_map.handle_cc(*cc, *value) {
+                    debug!(cc, "{:?} {}", parameter, parameter.display(value));
+                    if let Some(layer) = layer {
+                        parameter.set(&mut layer_presets[layer], value);
+                    }
+                    send_on(SynthEvent::Control(parameter, value));
+                }
+                // Learned bindings are kept for next time.
+                if let Some(parameter) = learning {
+                    info!(cc, "{} bound", parameter.name());
+                    if let Err(e) = midi_map.save(&midi_map_path) {
+                        warn!("Couldn't save the MIDI map to {}: {}", midi_map_path.display(), e);
+                    }
+                }
+            },
+            (0xC0, [program]) => { // Program Change event
+                let Some(layer) = layer else {
+                    trace!(channel, "no layer on this channel");
+                    return;
+                };
+                let Some(preset) = bank.presets.get(*program as usize) else {
+                    warn!(program, "no preset for program change");
+                    return;
+                };
+                info!(program, layer = layer + 1, "Switching to preset: {}", preset.name);
+                layer_presets[layer] = preset.clone();
+                send_on(SynthEvent::Preset(Box::new(preset.clone())));
+            },
+            (0xA0, [key, pressure]) => { // Polyphonic Key Pressure event
+                trace!(channel, key, pressure, "key pressure");
+                send(SynthEvent::Pressure { channel, key: Some(*key), pressure: *pressure as f32 / 127.0 });
+            },
+            (0xD0, [pressure]) => { // Channel Pressure event
+                trace!(channel, pressure, "channel pressure");
+                send(SynthEvent::Pressure { channel, key: None, pressure: *pressure as f32 / 127.0 });
+            },
+            (0xE0, [lsb, msb]) => { // Pitch Bend event
+                let bend = pitch_bend(*lsb, *msb);
+                trace!(channel, bend, "pitch bend");
+                send(SynthEvent::PitchBend { channel, bend });
+            },
+            (0xF8, []) => { // Timing Clock event
+                // Only pass on real changes, since each one rebuilds the template voice.
+                if let Some(tempo) = midi_clock.tick(Instant::now()) {
+                    if (tempo - clock_tempo).abs() >= 0.5 {
+                        clock_tempo = tempo;
+                        debug!(tempo, "MIDI clock tempo");
+                        for preset in layer_presets.iter_mut() {
+                            Parameter::Tempo.set(preset, tempo);
+                        }
+                        send(SynthEvent::Control(Parameter::Tempo, tempo));
+                    }
+                }
+            },
+            (0xFA..=0xFC, []) => midi_clock.reset(), // Start, Continue and Stop events
+            (0xF0, _) => { // System Exclusive event
+                // Sysex dumps and restores use the selected layer.
+                let layer = selected_layer.load(Ordering::Relaxed);
+                // Notes already sounding on a retuned key move to its new pitch.
+                let previous = tuning.clone();
+                if tuning.apply_mts(message) {
+                    let retunes = synth::retunes(&previous, &tuning);
+                    debug!(keys = retunes.len(), "retuned by MIDI Tuning Standard message");
+                    retunes.into_iter().for_each(send);
+                    return;
+                }
+                match sysex::decode(message) {
+                    Ok(Some(SysexMessage::DumpRequest)) => {
+                        let Some(midi_out) = midi_out.as_mut() else {
+                            warn!("sysex dump requested, but there is no --midi-out port to send it on");
+                            return;
+                        };
+                        let current = &layer_presets[layer];
+                        match sysex::encode_preset(current) {
+                            Ok(dump) => match midi_out.send(&dump) {
+                                Ok(()) => info!("Sent sysex dump of preset: {}", current.name),
+                                Err(e) => warn!("Couldn't send sysex dump: {}", e),
+                            },
+                            Err(e) => warn!("{}", e),
+                        }
+                    }
+                    Ok(Some(SysexMessage::PresetDump(preset))) => {
+                        info!("Restoring preset from sysex: {}", preset.name);
+                        layer_presets[layer] = (*preset).clone();
+                        send(SynthEvent::Preset(preset));
+                    }
+                    Ok(None) => trace!(?message, "ignored sysex message"),
+                    Err(e) => warn!("{}", e),
+                }
+            },
+            _ => trace!(?message, "ignored MIDI message"),
+        }
+    };
+
+    let mut midi_inputs = MidiInputs::new(config.midi_ports, handler)?;
+    midi_inputs.poll();
+    if midi_inputs.connected() == 0 {
+        warn!("No MIDI input connected yet, waiting for one to appear");
     }
 
-    Ok(())
-}
\ No newline at end of file
+    // The main thread watches for MIDI devices coming and going, reopens the
+    // output if it fails, and keeps an eye on the latency and render load,
+    // while the synth plays on the audio thread.
+    let mut latency_reported = false;
+    loop {
+        thread::sleep(MIDI_POLL_INTERVAL);
+        midi_inputs.poll();
+
+        // Whatever device now answers to the name may run at another rate,
+        // which the synth switches to with its notes still sounding.
+        if output.failed() {
+            _stream = None;
+            match audio::Output::open(device_name, config.sample_rate, config.buffer_size) {
+                Ok(reopened) => {
+                    let sample_rate = reopened.sample_rate();
+                    source.lock().unwrap_or_else(PoisonError::into_inner).set_sample_rate(sample_rate);
+                    match reopened.play(Arc::clone(&source)) {
+                        Ok(stream) => {
+                            info!(sample_rate, "Audio output reopened");
+                            _stream = Some(stream);
+                            output = reopened;
+                            latency_reported = false;
+                        }
+                        Err(e) => warn!("Couldn't restart the audio output: {}", e),
+                    }
+                }
+                Err(e) => warn!("Couldn't reopen the audio output: {}", e),
+            }
+        }
+
+        if let Some(latency) = output.measured_latency().filter(|_| !latency_reported) {
+            info!("Output latency {:.1} ms as the device reports it", latency.as_secs_f64() * 1000.0);
+            latency_reported = true;
+        }
+
+        let (load, per_voice) = (cpu_load.get() * 100.0, cpu_load.per_voice() * 100.0);
+        if load > CPU_LOAD_WARNING {
+            warn!("DSP load {:.0}% of the audio budget, {:.1}% per voice", load, per_voice);
+        } else {
+            debug!("DSP load {:.0}% of the audio budget, {:.1}% per voice", load, per_voice);
+        }
+        let ([peak_left, peak_right], [rms_left, rms_right]) = (master_meter.take_peaks(), master_meter.rms());
+        debug!(
+            "Output peak {:.1}/{:.1} dBFS, RMS {:.1}/{:.1} dBFS",
+            20.0 * peak_left.log10(),
+            20.0 * peak_right.log10(),
+            20.0 * rms_left.log10(),
+            20.0 * rms_right.log10()
+        );
+        if master_meter.take_clipped() {
+            debug!("Output hit the ceiling, limiter taking {:.1} dB off", master_meter.gain_reduction());
+        }
+        let [dc_left, dc_right] = master_meter.dc_offset();
+        if dc_left.abs().max(dc_right.abs()) > DC_OFFSET_WARNING {
+            warn!("DC offset of {:.3}/{:.3} on the output", dc_left, dc_right);
+        }
+    }
+}
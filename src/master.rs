@@ -0,0 +1,234 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::compressor::{Compressor, CompressorSettings};
+use crate::eq::{Eq, EqSettings};
+
+const LOOKAHEAD: f32 = 0.0015; // Seconds the limiter sees peaks coming
+const MAX_LOOKAHEAD: usize = 288; // Frames of LOOKAHEAD at 192 kHz
+const LIMITER_RELEASE: f32 = 0.05; // Seconds, time constant of the limiter letting go
+const SOFT_KNEE: f32 = 0.7; // Fraction of the ceiling the soft clipper starts bending at
+const RMS_WINDOW: f32 = 0.3; // Seconds, time constant of the RMS meter
+const DC_WINDOW: f32 = 1.0; // Seconds, time constant of the DC offset meter
+
+// Processing on the mixed output of every layer.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MasterSettings {
+    pub eq: EqSettings,
+    pub compressor: Option<CompressorSettings>,
+    pub ceiling: f32,    // dBFS the limiter holds peaks under
+    pub soft_clip: bool, // Rounds peaks off before the limiter, for some grit instead of pumping
+}
+
+impl Default for MasterSettings {
+    fn default() -> Self {
+        Self {
+            eq: EqSettings::default(),
+            compressor: None,
+            ceiling: -0.3,
+            soft_clip: false,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Readings {
+    gain_reduction: AtomicU32,
+    peak: [AtomicU32; 2],
+    rms: [AtomicU32; 2],
+    dc_offset: [AtomicU32; 2],
+    clipped: AtomicBool,
+}
+
+// What the master bus is doing, read from any thread once it's playing.
+// Levels are linear and for the left and right channels of the output.
+#[derive(Clone, Debug, Default)]
+pub struct MasterMeter {
+    readings: Arc<Readings>,
+}
+
+impl MasterMeter {
+    // dB the limiter took off the loudest moment of the last block, 0.0
+    // when it isn't limiting.
+    pub fn gain_reduction(&self) -> f32 {
+        f32::from_bits(self.readings.gain_reduction.load(Ordering::Relaxed))
+    }
+
+    // The loudest sample since the last call.
+    pub fn take_peaks(&self) -> [f32; 2] {
+        self.readings.peak.each_ref().map(|peak| f32::from_bits(peak.swap(0, Ordering::Relaxed)))
+    }
+
+    pub fn rms(&self) -> [f32; 2] {
+        self.readings.rms.each_ref().map(|rms| f32::from_bits(rms.load(Ordering::Relaxed)))
+    }
+
+    pub fn dc_offset(&self) -> [f32; 2] {
+        self.readings.dc_offset.each_ref().map(|dc| f32::from_bits(dc.load(Ordering::Relaxed)))
+    }
+
+    // Whether the mix went over the ceiling, and so needed limiting or
+    // clipping, since the last call.
+    pub fn take_clipped(&self) -> bool {
+        self.readings.clipped.swap(false, Ordering::Relaxed)
+    }
+}
+
+// Shapes the output with the EQ and compressor, then keeps it under the
+// ceiling with a lookahead brickwall limiter, which delays the signal long
+// enough to turn the gain down before each peak arrives rather than after.
+// Anything left over is clipped.
+pub struct MasterBus {
+    eq: Eq,
+    compressor: Option<Compressor>,
+    ceiling: f32, // Linear
+    soft_clip: bool,
+    sample_rate: u32,
+    lookahead: usize, // Frames
+    delay: [f32; MAX_LOOKAHEAD * 2],
+    position: usize,
+    gain: f32,
+    target: f32, // Lowest gain anything in the delay line needs
+    step: f32,   // Gain taken off per frame on the way down to it
+    hold: usize, // Frames until the last peak that needed limiting has passed
+    release: f32,
+    mean_square: [f32; 2],
+    dc: [f32; 2],
+    rms_coefficient: f32,
+    dc_coefficient: f32,
+    meter: MasterMeter,
+}
+
+impl MasterBus {
+    pub fn new(settings: &MasterSettings, sample_rate: u32) -> MasterBus {
+        let mut master = MasterBus {
+            eq: Eq::new(&settings.eq, sample_rate),
+            compressor: settings.compressor.map(|compressor| Compressor::new(&compressor, sample_rate)),
+            ceiling: 10.0_f32.powf(settings.ceiling.min(0.0) / 20.0),
+            soft_clip: settings.soft_clip,
+            sample_rate,
+            lookahead: 1,
+            delay: [0.0; MAX_LOOKAHEAD * 2],
+            position: 0,
+            gain: 1.0,
+            target: 1.0,
+            step: 0.0,
+            hold: 0,
+            release: 0.0,
+            mean_square: [0.0; 2],
+            dc: [0.0; 2],
+            rms_coefficient: 0.0,
+            dc_coefficient: 0.0,
+            meter: MasterMeter::default(),
+        };
+        master.set_sample_rate(sample_rate);
+        master
+    }
+
+    pub fn meter(&self) -> MasterMeter {
+        self.meter.clone()
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.eq.set_sample_rate(sample_rate);
+        if let Some(compressor) = &mut self.compressor {
+            compressor.set_sample_rate(sample_rate);
+        }
+        self.lookahead = ((LOOKAHEAD * sample_rate as f32).round() as usize).clamp(1, MAX_LOOKAHEAD);
+        self.position %= self.lookahead;
+        self.release = 1.0 - (-1.0 / (LIMITER_RELEASE * sample_rate as f32)).exp();
+        self.rms_coefficient = 1.0 - (-1.0 / (RMS_WINDOW * sample_rate as f32)).exp();
+        self.dc_coefficient = 1.0 - (-1.0 / (DC_WINDOW * sample_rate as f32)).exp();
+    }
+
+    // Processes interleaved stereo `buffer` in place.
+    pub fn process(&mut self, buffer: &mut [f32]) {
+        self.eq.process(buffer);
+        if let Some(compressor) = &mut self.compressor {
+            compressor.process(buffer);
+        }
+        let mut lowest_gain: f32 = 1.0;
+        let mut peak = [0.0_f32; 2];
+        let mut clipped = false;
+        for frame in buffer.chunks_exact_mut(2) {
+            let (mut left, mut right) = (frame[0], frame[1]);
+            clipped |= left.abs().max(right.abs()) > self.ceiling;
+            if self.soft_clip {
+                left = soft_clip(left, self.ceiling);
+                right = soft_clip(right, self.ceiling);
+            }
+            let gain = self.limit(left.abs().max(right.abs()));
+            lowest_gain = lowest_gain.min(gain);
+
+            let delayed = &mut self.delay[self.position * 2..self.position * 2 + 2];
+            frame[0] = (delayed[0] * gain).clamp(-self.ceiling, self.ceiling);
+            frame[1] = (delayed[1] * gain).clamp(-self.ceiling, self.ceiling);
+            delayed[0] = left;
+            delayed[1] = right;
+            self.position = (self.position + 1) % self.lookahead;
+
+            for (channel, &sample) in frame.iter().enumerate() {
+                peak[channel] = peak[channel].max(sample.abs());
+                self.mean_square[channel] += (sample * sample - self.mean_square[channel]) * self.rms_coefficient;
+                self.dc[channel] += (sample - self.dc[channel]) * self.dc_coefficient;
+            }
+        }
+
+        let readings = &self.meter.readings;
+        let reduction = -20.0 * lowest_gain.log10();
+        readings.gain_reduction.store(reduction.max(0.0).to_bits(), Ordering::Relaxed);
+        for (channel, peak) in peak.iter().enumerate() {
+            // Non-negative floats order the same way as their bits.
+            readings.peak[channel].fetch_max(peak.to_bits(), Ordering::Relaxed);
+            readings.rms[channel].store(self.mean_square[channel].sqrt().to_bits(), Ordering::Relaxed);
+            readings.dc_offset[channel].store(self.dc[channel].to_bits(), Ordering::Relaxed);
+        }
+        if clipped {
+            readings.clipped.store(true, Ordering::Relaxed);
+        }
+    }
+
+    // Takes in a frame's `peak` as it enters the delay line, and returns the
+    // gain for the frame leaving it.
+    fn limit(&mut self, peak: f32) -> f32 {
+        if peak > self.ceiling {
+            let needed = self.ceiling / peak;
+            if needed < self.target {
+                self.target = needed;
+                // Down in time for this peak to come out of the delay line.
+                self.step = self.step.max((self.gain - needed) / self.lookahead as f32);
+            }
+            self.hold = self.lookahead + 1;
+        }
+        if self.hold > 0 {
+            self.hold -= 1;
+        } else {
+            self.target = 1.0;
+            self.step = 0.0;
+        }
+        if self.gain > self.target {
+            self.gain = (self.gain - self.step).max(self.target);
+        } else if self.target - self.gain > 1e-3 {
+            self.gain += (self.target - self.gain) * self.release;
+        } else {
+            self.gain = self.target;
+        }
+        self.gain
+    }
+}
+
+// Leaves samples under SOFT_KNEE of `ceiling` alone and bends the rest
+// smoothly towards it.
+fn soft_clip(sample: f32, ceiling: f32) -> f32 {
+    let knee = ceiling * SOFT_KNEE;
+    let magnitude = sample.abs();
+    if magnitude <= knee {
+        return sample;
+    }
+    let range = ceiling - knee;
+    (knee + range * ((magnitude - knee) / range).tanh()).copysign(sample)
+}
@@ -0,0 +1,31 @@
+use serde::{Deserialize, Serialize};
+
+// Where the wheel sits, from -1.0 fully down to 1.0 fully up; the preset's
+// bend range turns it into semitones.
+pub fn pitch_bend(lsb: u8, msb: u8) -> f32 {
+    let value = (((msb as i32) << 7) | lsb as i32) - 8192;
+    value as f32 / 8192.0
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum VelocityCurve {
+    Linear,
+    Exponential,
+    Soft,
+    Hard,
+    Fixed,
+}
+
+impl VelocityCurve {
+    // Maps a MIDI velocity to a 0.0 to 1.0 response.
+    pub fn apply(&self, velocity: u8) -> f32 {
+        let velocity = velocity as f32 / 127.0;
+        match self {
+            VelocityCurve::Linear => velocity,
+            VelocityCurve::Exponential => ((4.0 * velocity).exp() - 1.0) / (4.0_f32.exp() - 1.0),
+            VelocityCurve::Soft => velocity.sqrt(),
+            VelocityCurve::Hard => velocity * velocity,
+            VelocityCurve::Fixed => 1.0,
+        }
+    }
+}
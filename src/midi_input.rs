@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use midir::{MidiInput, MidiInputConnection, MidiInputPort, MidiOutput, MidiOutputConnection};
+use tracing::{info, warn};
+
+use crate::error::SynthError;
+
+const CLIENT_NAME: &str = "wavetable_synth";
+
+// Names of the MIDI input ports currently available.
+pub fn input_ports() -> Result<Vec<String>, SynthError> {
+    let midi_in = MidiInput::new(CLIENT_NAME)?;
+    Ok(port_names(&midi_in))
+}
+
+// Connects the first output port whose name contains `wanted`, for answering
+// sysex dump requests.
+pub fn connect_output(wanted: &str) -> Result<MidiOutputConnection, SynthError> {
+    let midi_out = MidiOutput::new(CLIENT_NAME)?;
+    let port = midi_out
+        .ports()
+        .into_iter()
+        .find(|port| midi_out.port_name(port).is_ok_and(|name| name.contains(wanted)))
+        .ok_or_else(|| SynthError::NoMidiOutput(String::from(wanted)))?;
+    Ok(midi_out.connect(&port, CLIENT_NAME)?)
+}
+
+fn port_names(midi_in: &MidiInput) -> Vec<String> {
+    midi_in.ports().iter().filter_map(|port| midi_in.port_name(port).ok()).collect()
+}
+
+// Keeps every port whose name contains one of `wanted` connected, or every
+// port if `wanted` is empty. Ports that vanish are dropped and picked up again
+// by `poll` when they come back, with every connection sharing one handler.
+pub struct MidiInputs<F: FnMut(&[u8]) + Send + 'static> {
+    wanted: Vec<String>,
+    scanner: MidiInput,
+    connections: HashMap<String, MidiInputConnection<()>>,
+    handler: Arc<Mutex<F>>,
+}
+
+impl<F: FnMut(&[u8]) + Send + 'static> MidiInputs<F> {
+    pub fn new(wanted: Vec<String>, handler: F) -> Result<Self, SynthError> {
+        Ok(Self {
+            wanted,
+            scanner: MidiInput::new(CLIENT_NAME)?,
+            connections: HashMap::new(),
+            handler: Arc::new(Mutex::new(handler)),
+        })
+    }
+
+    pub fn connected(&self) -> usize {
+        self.connections.len()
+    }
+
+    // Connects any wanted ports that have appeared and forgets any that have
+    // gone away.
+    pub fn poll(&mut self) {
+        let available = port_names(&self.scanner);
+
+        self.connections.retain(|name, _| {
+            let present = available.contains(name);
+            if !present {
+                warn!("MIDI input disconnected: {}", name);
+            }
+            present
+        });
+
+        for name in available {
+            if self.connections.contains_key(&name) || !self.wants(&name) {
+                continue;
+            }
+            match self.connect(&name) {
+                Ok(connection) => {
+                    info!("Listening on: {}", name);
+                    self.connections.insert(name, connection);
+                }
+                // Most likely unplugged mid-scan; the next poll retries.
+                Err(e) => warn!("Couldn't connect to {}: {}", name, e),
+            }
+        }
+    }
+
+    fn wants(&self, name: &str) -> bool {
+        self.wanted.is_empty() || self.wanted.iter().any(|wanted| name.contains(wanted.as_str()))
+    }
+
+    fn connect(&self, name: &str) -> Result<MidiInputConnection<()>, SynthError> {
+        // Connecting consumes the client, so each port gets its own.
+        let midi_in = MidiInput::new(CLIENT_NAME)?;
+        let port = find_port(&midi_in, name).ok_or(SynthError::NoMidiInput)?;
+        let handler = Arc::clone(&self.handler);
+        // A handler that panicked on one port still gets the next message,
+        // rather than every port going quiet.
+        let connection = midi_in.connect(&port, CLIENT_NAME, move |_, message, _| {
+            let mut handler = handler.lock().unwrap_or_else(PoisonError::into_inner);
+            handler(message);
+        }, ())?;
+        Ok(connection)
+    }
+}
+
+fn find_port(midi_in: &MidiInput, name: &str) -> Option<MidiInputPort> {
+    midi_in
+        .ports()
+        .into_iter()
+        .find(|port| midi_in.port_name(port).is_ok_and(|port_name| port_name == name))
+}
@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::SynthError;
+use crate::json::{load_json, save_json};
+use crate::params::Parameter;
+
+pub const MOD_WHEEL: u8 = 1;
+pub const SUSTAIN: u8 = 64;
+pub const SOSTENUTO: u8 = 66;
+pub const SOFT_PEDAL: u8 = 67;
+pub const HOLD: u8 = 69; // Latches notes while down
+pub const TIMBRE: u8 = 74; // MPE's third dimension, usually brightness
+pub const ALL_SOUND_OFF: u8 = 120;
+pub const ALL_NOTES_OFF: u8 = 123;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MidiMap {
+    bindings: HashMap<u8, Parameter>,
+    #[serde(skip)]
+    learning: Option<Parameter>,
+}
+
+impl Default for MidiMap {
+    fn default() -> Self {
+        let mut map = Self {
+            bindings: HashMap::new(),
+            learning: None,
+        };
+        map.bind(MOD_WHEEL, Parameter::WavetablePosition);
+        map
+    }
+}
+
+impl MidiMap {
+    pub fn load(path: &Path) -> Result<MidiMap, SynthError> {
+        load_json(path)
+    }
+
+    // Creates the folder if need be, as the default is in the config
+    // directory, which may not exist yet.
+    pub fn save(&self, path: &Path) -> Result<(), SynthError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        save_json(self, path)
+    }
+
+    // Each parameter follows at most one controller, so binding it moves it.
+    pub fn bind(&mut self, cc: u8, parameter: Parameter) {
+        self.bindings.retain(|_, bound| *bound != parameter);
+        self.bindings.insert(cc, parameter);
+    }
+
+    pub fn unbind(&mut self, cc: u8) {
+        self.bindings.remove(&cc);
+    }
+
+    pub fn parameter(&self, cc: u8) -> Option<Parameter> {
+        self.bindings.get(&cc).copied()
+    }
+
+    // Binds `parameter` to whichever controller moves next.
+    pub fn learn(&mut self, parameter: Parameter) {
+        self.learning = Some(parameter);
+    }
+
+    // The parameter waiting for a controller to move, if any.
+    pub fn learning(&self) -> Option<Parameter> {
+        self.learning
+    }
+
+    // Maps an incoming control change to the parameter it drives and the
+    // value scaled along that parameter's curve.
+    pub fn handle_cc(&mut self, cc: u8, value: u8) -> Option<(Parameter, f32)> {
+        if let Some(parameter) = self.learning.take() {
+            self.bind(cc, parameter);
+        }
+
+        let parameter = self.parameter(cc)?;
+        Some((parameter, parameter.from_normalized(value as f32 / 127.0)))
+    }
+}
@@ -1,2 +0,0 @@
-pub mod envelope;
-pub mod wavetable_oscillator;
@@ -0,0 +1,106 @@
+use std::f32::consts::{FRAC_PI_4, SQRT_2};
+
+use serde::{Deserialize, Serialize};
+
+pub const MOD_SLOTS: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ModSource {
+    Lfo,      // -1.0 to 1.0
+    Lfo2,     // -1.0 to 1.0
+    Envelope, // 0.0 to 1.0
+    Velocity, // 0.0 to 1.0, after the velocity curve
+    ModWheel, // 0.0 to 1.0
+    Pressure, // 0.0 to 1.0, from channel pressure or the key's own aftertouch
+    Timbre,   // 0.0 to 1.0, from CC 74 on the note's channel, MPE's third dimension
+    Keytrack, // Octaves from middle C divided by five
+    Random,   // -1.0 to 1.0, fixed per note
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ModDestination {
+    Pitch,    // Semitones
+    Position, // Wavetable position, 0.0 to 1.0
+    Pan,      // -1.0 to 1.0
+    Volume,   // Fraction of the voice's level
+}
+
+// Routes `source` to `destination`, scaled by a bipolar `amount` in the
+// destination's units.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ModSlot {
+    pub source: ModSource,
+    pub destination: ModDestination,
+    pub amount: f32,
+}
+
+// Source values for one sample of a voice.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ModSources {
+    pub lfo: f32,
+    pub lfo2: f32,
+    pub envelope: f32,
+    pub velocity: f32,
+    pub mod_wheel: f32,
+    pub pressure: f32,
+    pub timbre: f32,
+    pub keytrack: f32,
+    pub random: f32,
+}
+
+// Summed offsets for each destination.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Modulation {
+    pub pitch: f32,
+    pub position: f32,
+    pub pan: f32,
+    pub volume: f32,
+}
+
+impl Default for ModSlot {
+    fn default() -> Self {
+        Self {
+            source: ModSource::Lfo,
+            destination: ModDestination::Pitch,
+            amount: 0.0,
+        }
+    }
+}
+
+impl ModSources {
+    fn get(&self, source: ModSource) -> f32 {
+        match source {
+            ModSource::Lfo => self.lfo,
+            ModSource::Lfo2 => self.lfo2,
+            ModSource::Envelope => self.envelope,
+            ModSource::Velocity => self.velocity,
+            ModSource::ModWheel => self.mod_wheel,
+            ModSource::Pressure => self.pressure,
+            ModSource::Timbre => self.timbre,
+            ModSource::Keytrack => self.keytrack,
+            ModSource::Random => self.random,
+        }
+    }
+}
+
+impl Modulation {
+    // Constant-power gains that move a stereo signal by `pan`, unity at centre.
+    pub fn pan_gains(&self) -> (f32, f32) {
+        let angle = (self.pan.clamp(-1.0, 1.0) + 1.0) * FRAC_PI_4;
+        (angle.cos() * SQRT_2, angle.sin() * SQRT_2)
+    }
+}
+
+pub fn evaluate(slots: &[ModSlot], sources: &ModSources) -> Modulation {
+    let mut modulation = Modulation::default();
+    for slot in slots {
+        let value = sources.get(slot.source) * slot.amount;
+        match slot.destination {
+            ModDestination::Pitch => modulation.pitch += value,
+            ModDestination::Position => modulation.position += value,
+            ModDestination::Pan => modulation.pan += value,
+            ModDestination::Volume => modulation.volume += value,
+        }
+    }
+    modulation
+}
@@ -0,0 +1,362 @@
+use serde::{Deserialize, Serialize};
+
+use crate::clock::{MAX_TEMPO, MIN_TEMPO};
+use crate::preset::Preset;
+use crate::synth::MAX_VOICES;
+
+// How a controller's travel is spread across a parameter's range.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Curve {
+    Linear,
+    Exponential, // Finer control at the low end, for times and rates
+}
+
+// Every parameter that can be changed while playing, through the MIDI map or
+// the randomiser, with its range, units and how to read and write it in a
+// preset.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Parameter {
+    Volume,
+    Pan,
+    VoiceSpread,
+    WaveLevel,
+    WavetablePosition,
+    PluckDamping,
+    PluckBrightness,
+    FmRatio,
+    FmIndex,
+    FmEnvAmount,
+    FmEnvDecay,
+    FineTune,
+    PitchBendRange,
+    Drift,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    UnisonDetune,
+    UnisonSpread,
+    SubLevel,
+    SampleLevel,
+    GlideTime,
+    PitchEnvAmount,
+    PitchEnvDecay,
+    LfoRate,
+    LfoDepth,
+    Tempo,
+    Polyphony,
+}
+
+impl Parameter {
+    pub const ALL: [Parameter; 29] = [
+        Parameter::Volume,
+        Parameter::Pan,
+        Parameter::VoiceSpread,
+        Parameter::WaveLevel,
+        Parameter::WavetablePosition,
+        Parameter::PluckDamping,
+        Parameter::PluckBrightness,
+        Parameter::FmRatio,
+        Parameter::FmIndex,
+        Parameter::FmEnvAmount,
+        Parameter::FmEnvDecay,
+        Parameter::FineTune,
+        Parameter::PitchBendRange,
+        Parameter::Drift,
+        Parameter::Attack,
+        Parameter::Decay,
+        Parameter::Sustain,
+        Parameter::Release,
+        Parameter::UnisonDetune,
+        Parameter::UnisonSpread,
+        Parameter::SubLevel,
+        Parameter::SampleLevel,
+        Parameter::GlideTime,
+        Parameter::PitchEnvAmount,
+        Parameter::PitchEnvDecay,
+        Parameter::LfoRate,
+        Parameter::LfoDepth,
+        Parameter::Tempo,
+        Parameter::Polyphony,
+    ];
+
+    // Parameters `Preset::randomize` changes, leaving level, tuning, tempo
+    // and the LFOs alone.
+    pub const RANDOMIZED: [Parameter; 12] = [
+        Parameter::VoiceSpread,
+        Parameter::WavetablePosition,
+        Parameter::Attack,
+        Parameter::Decay,
+        Parameter::Sustain,
+        Parameter::Release,
+        Parameter::UnisonDetune,
+        Parameter::UnisonSpread,
+        Parameter::SubLevel,
+        Parameter::GlideTime,
+        Parameter::PitchEnvAmount,
+        Parameter::PitchEnvDecay,
+    ];
+
+    // The (min, max) a controller sweeps the parameter across.
+    pub fn range(&self) -> (f32, f32) {
+        match self {
+            Parameter::Pan => (-1.0, 1.0),
+            Parameter::FineTune => (-100.0, 100.0),
+            Parameter::PitchBendRange => (0.0, 24.0),
+            Parameter::Drift => (0.0, 50.0),
+            Parameter::Attack | Parameter::Decay | Parameter::Release => (0.0, 5.0),
+            Parameter::GlideTime => (0.0, 2.0),
+            Parameter::FmRatio => (0.5, 16.0),
+            Parameter::FmIndex | Parameter::FmEnvAmount => (0.0, 10.0),
+            Parameter::FmEnvDecay => (0.0, 5.0),
+            Parameter::UnisonDetune => (0.0, 100.0),
+            Parameter::PitchEnvAmount => (-48.0, 48.0),
+            Parameter::PitchEnvDecay => (0.0, 2.0),
+            Parameter::LfoRate => (0.01, 20.0),
+            Parameter::Tempo => (MIN_TEMPO, MAX_TEMPO),
+            Parameter::Polyphony => (1.0, MAX_VOICES as f32),
+            _ => (0.0, 1.0),
+        }
+    }
+
+    pub fn curve(&self) -> Curve {
+        match self {
+            Parameter::Attack
+            | Parameter::Decay
+            | Parameter::Release
+            | Parameter::GlideTime
+            | Parameter::PitchEnvDecay
+            | Parameter::FmRatio
+            | Parameter::FmEnvDecay
+            | Parameter::LfoRate => Curve::Exponential,
+            _ => Curve::Linear,
+        }
+    }
+
+    // Maps 0.0 to 1.0, as from a controller, into the parameter's range
+    // along its curve. Exponential curves still reach zero at the bottom.
+    pub fn from_normalized(&self, x: f32) -> f32 {
+        let (min, max) = self.range();
+        let x = x.clamp(0.0, 1.0);
+        match self.curve() {
+            Curve::Linear => min + (max - min) * x,
+            Curve::Exponential if min > 0.0 => min * (max / min).powf(x),
+            Curve::Exponential => min + (max - min) * x * x * x,
+        }
+    }
+
+    // The value in the init patch.
+    pub fn default(&self) -> f32 {
+        self.get(&Preset::default())
+    }
+
+    // A narrower range that random patches stay within, so they come out
+    // playable rather than all five-second attacks and wild detune.
+    pub fn random_range(&self) -> (f32, f32) {
+        match self {
+            Parameter::Attack => (0.0, 1.0),
+            Parameter::Decay | Parameter::Release => (0.05, 2.0),
+            Parameter::Sustain => (0.2, 1.0),
+            Parameter::UnisonDetune => (0.0, 40.0),
+            Parameter::GlideTime => (0.0, 0.3),
+            Parameter::PitchEnvAmount => (-12.0, 12.0),
+            Parameter::PitchEnvDecay => (0.0, 0.5),
+            _ => self.range(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Parameter::Volume => "Volume",
+            Parameter::Pan => "Pan",
+            Parameter::VoiceSpread => "Voice spread",
+            Parameter::WaveLevel => "Wave level",
+            Parameter::WavetablePosition => "Wavetable position",
+            Parameter::PluckDamping => "Pluck damping",
+            Parameter::PluckBrightness => "Pluck brightness",
+            Parameter::FmRatio => "FM ratio",
+            Parameter::FmIndex => "FM index",
+            Parameter::FmEnvAmount => "FM env amount",
+            Parameter::FmEnvDecay => "FM env decay",
+            Parameter::FineTune => "Fine tune",
+            Parameter::PitchBendRange => "Pitch bend range",
+            Parameter::Drift => "Drift",
+            Parameter::Attack => "Attack",
+            Parameter::Decay => "Decay",
+            Parameter::Sustain => "Sustain",
+            Parameter::Release => "Release",
+            Parameter::UnisonDetune => "Unison detune",
+            Parameter::UnisonSpread => "Unison spread",
+            Parameter::SubLevel => "Sub level",
+            Parameter::SampleLevel => "Sample level",
+            Parameter::GlideTime => "Glide time",
+            Parameter::PitchEnvAmount => "Pitch env amount",
+            Parameter::PitchEnvDecay => "Pitch env decay",
+            Parameter::LfoRate => "LFO rate",
+            Parameter::LfoDepth => "LFO depth",
+            Parameter::Tempo => "Tempo",
+            Parameter::Polyphony => "Polyphony",
+        }
+    }
+
+    // One line on what the parameter does, for help text.
+    pub fn description(&self) -> &'static str {
+        match self {
+            Parameter::Volume => "Output level of each voice",
+            Parameter::Pan => "Stereo position of the voices",
+            Parameter::VoiceSpread => "How far successive notes are spread from the pan position",
+            Parameter::WaveLevel => "Level of the wavetable, string or FM carrier under the sub and sample",
+            Parameter::WavetablePosition => "Which frame of the wavetable plays, morphing between neighbours",
+            Parameter::PluckDamping => "How quickly a plucked string loses its highs",
+            Parameter::PluckBrightness => "How bright a plucked string starts",
+            Parameter::FmRatio => "Frequency of the FM modulator as a multiple of the note",
+            Parameter::FmIndex => "Depth of frequency modulation once the note settles",
+            Parameter::FmEnvAmount => "Extra FM depth at the start of each note",
+            Parameter::FmEnvDecay => "Time constant of the FM depth settling",
+            Parameter::FineTune => "Detunes every note",
+            Parameter::PitchBendRange => "How far the pitch wheel bends notes either way",
+            Parameter::Drift => "How far each oscillator's pitch slowly wanders",
+            Parameter::Attack => "Time for a note to rise to full level",
+            Parameter::Decay => "Time to fall from full level to the sustain level",
+            Parameter::Sustain => "Level held while the key is down",
+            Parameter::Release => "Time to fade out after the key is let go",
+            Parameter::UnisonDetune => "Total spread of pitch across the unison stack",
+            Parameter::UnisonSpread => "Stereo width of the unison stack",
+            Parameter::SubLevel => "Level of the sub-oscillator",
+            Parameter::SampleLevel => "Level of the sample layered under the wavetable",
+            Parameter::GlideTime => "Time to slide from the last note to the new one",
+            Parameter::PitchEnvAmount => "How far each note starts from its pitch",
+            Parameter::PitchEnvDecay => "Time constant of the slide back to pitch",
+            Parameter::LfoRate => "Speed of the first LFO when not tempo-synced",
+            Parameter::LfoDepth => "Amount of the first LFO, up to an octave of vibrato or full tremolo",
+            Parameter::Tempo => "Tempo that synced LFOs follow",
+            Parameter::Polyphony => "Notes that sound at once before new ones take over old voices",
+        }
+    }
+
+    // Formats `value` in the parameter's units: ms or s for times, dB for
+    // levels, cents or semitones for pitch, Hz for rates.
+    pub fn display(&self, value: f32) -> String {
+        match self {
+            Parameter::Volume
+            | Parameter::WaveLevel
+            | Parameter::Sustain
+            | Parameter::SubLevel
+            | Parameter::SampleLevel => {
+                if value > 0.0 {
+                    format!("{:.1} dB", 20.0 * value.log10())
+                } else {
+                    String::from("-inf dB")
+                }
+            }
+            Parameter::Pan if value.abs() < 0.005 => String::from("C"),
+            Parameter::Pan if value < 0.0 => format!("{:.0}L", -value * 100.0),
+            Parameter::Pan => format!("{:.0}R", value * 100.0),
+            Parameter::Attack
+            | Parameter::Decay
+            | Parameter::Release
+            | Parameter::GlideTime
+            | Parameter::PitchEnvDecay
+            | Parameter::FmEnvDecay => {
+                if value < 1.0 {
+                    format!("{:.0} ms", value * 1000.0)
+                } else {
+                    format!("{:.2} s", value)
+                }
+            }
+            Parameter::FineTune => format!("{:+.1} cents", value),
+            Parameter::FmRatio => format!("{:.2}x", value),
+            Parameter::FmIndex | Parameter::FmEnvAmount => format!("{:.2} rad", value),
+            Parameter::UnisonDetune | Parameter::Drift => format!("{:.1} cents", value),
+            Parameter::PitchEnvAmount => format!("{:+.1} st", value),
+            Parameter::PitchBendRange => format!("{:.1} st", value),
+            Parameter::LfoRate => format!("{:.2} Hz", value),
+            Parameter::Tempo => format!("{:.1} BPM", value),
+            Parameter::Polyphony => format!("{:.0} voices", value),
+            Parameter::VoiceSpread
+            | Parameter::WavetablePosition
+            | Parameter::PluckDamping
+            | Parameter::PluckBrightness
+            | Parameter::UnisonSpread
+            | Parameter::LfoDepth => format!("{:.0}%", value * 100.0),
+        }
+    }
+
+    pub fn get(&self, preset: &Preset) -> f32 {
+        match self {
+            Parameter::Volume => preset.volume,
+            Parameter::Pan => preset.pan,
+            Parameter::VoiceSpread => preset.voice_spread,
+            Parameter::WaveLevel => preset.wave_level,
+            Parameter::WavetablePosition => preset.wavetable_position,
+            Parameter::PluckDamping => preset.pluck_damping,
+            Parameter::PluckBrightness => preset.pluck_brightness,
+            Parameter::FmRatio => preset.fm_ratio,
+            Parameter::FmIndex => preset.fm_index,
+            Parameter::FmEnvAmount => preset.fm_env_amount,
+            Parameter::FmEnvDecay => preset.fm_env_decay,
+            Parameter::FineTune => preset.fine_tune,
+            Parameter::PitchBendRange => preset.pitch_bend_range,
+            Parameter::Drift => preset.drift,
+            Parameter::Attack => preset.attack,
+            Parameter::Decay => preset.decay,
+            Parameter::Sustain => preset.sustain,
+            Parameter::Release => preset.release,
+            Parameter::UnisonDetune => preset.unison_detune,
+            Parameter::UnisonSpread => preset.unison_spread,
+            Parameter::SubLevel => preset.sub_level,
+            Parameter::SampleLevel => preset.sample_level,
+            Parameter::GlideTime => preset.glide_time,
+            Parameter::PitchEnvAmount => preset.pitch_env_amount,
+            Parameter::PitchEnvDecay => preset.pitch_env_decay,
+            Parameter::LfoRate => preset.lfo.as_ref().map_or(0.0, |lfo| lfo.rate),
+            Parameter::LfoDepth => preset.lfo.as_ref().map_or(0.0, |lfo| lfo.depth / lfo.target.max_depth()),
+            Parameter::Tempo => preset.tempo,
+            Parameter::Polyphony => preset.polyphony as f32,
+        }
+    }
+
+    pub fn set(&self, preset: &mut Preset, value: f32) {
+        let (min, max) = self.range();
+        let value = value.clamp(min, max);
+        match self {
+            Parameter::Volume => preset.volume = value,
+            Parameter::Pan => preset.pan = value,
+            Parameter::VoiceSpread => preset.voice_spread = value,
+            Parameter::WaveLevel => preset.wave_level = value,
+            Parameter::WavetablePosition => preset.wavetable_position = value,
+            Parameter::PluckDamping => preset.pluck_damping = value,
+            Parameter::PluckBrightness => preset.pluck_brightness = value,
+            Parameter::FmRatio => preset.fm_ratio = value,
+            Parameter::FmIndex => preset.fm_index = value,
+            Parameter::FmEnvAmount => preset.fm_env_amount = value,
+            Parameter::FmEnvDecay => preset.fm_env_decay = value,
+            Parameter::FineTune => preset.fine_tune = value,
+            Parameter::PitchBendRange => preset.pitch_bend_range = value,
+            Parameter::Drift => preset.drift = value,
+            Parameter::Attack => preset.attack = value,
+            Parameter::Decay => preset.decay = value,
+            Parameter::Sustain => preset.sustain = value,
+            Parameter::Release => preset.release = value,
+            Parameter::UnisonDetune => preset.unison_detune = value,
+            Parameter::UnisonSpread => preset.unison_spread = value,
+            Parameter::SubLevel => preset.sub_level = value,
+            Parameter::SampleLevel => preset.sample_level = value,
+            Parameter::GlideTime => preset.glide_time = value,
+            Parameter::PitchEnvAmount => preset.pitch_env_amount = value,
+            Parameter::PitchEnvDecay => preset.pitch_env_decay = value,
+            Parameter::LfoRate => {
+                if let Some(lfo) = preset.lfo.as_mut() {
+                    lfo.rate = value;
+                }
+            }
+            Parameter::LfoDepth => {
+                if let Some(lfo) = preset.lfo.as_mut() {
+                    lfo.depth = value * lfo.target.max_depth();
+                }
+            }
+            Parameter::Tempo => preset.tempo = value,
+            Parameter::Polyphony => preset.polyphony = value.round() as usize,
+        }
+    }
+}
@@ -0,0 +1,289 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::de::Error as _;
+use serde::{Deserialize, Serialize};
+
+use crate::error::SynthError;
+use crate::json::{load_json, save_json};
+use crate::master::MasterSettings;
+use crate::preset::Preset;
+use crate::sampler::Sample;
+use crate::synth::{Synth, SynthEvent, BLOCK_SIZE};
+use crate::wavetable::Wavetable;
+
+pub const MAX_LAYERS: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum LayerMode {
+    Layer, // Every key plays every layer
+    Split, // Keys below split_key play the first layer, the rest the second
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Layer {
+    pub preset: Preset,
+    pub volume: f32,
+    pub pan: f32,             // -1.0 to 1.0, balancing the layer's stereo output
+    pub channel: Option<u8>,  // MIDI channel 1 to 16 to listen on; every channel if unset
+}
+
+impl Default for Layer {
+    fn default() -> Self {
+        Self {
+            preset: Preset::default(),
+            volume: 1.0,
+            pan: 0.0,
+            channel: None,
+        }
+    }
+}
+
+// Up to two presets played together from one keyboard, stacked or split.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Performance {
+    pub name: String,
+    pub mode: LayerMode,
+    pub split_key: u8,
+    pub layers: Vec<Layer>,
+    pub master: MasterSettings,
+}
+
+impl Default for Performance {
+    fn default() -> Self {
+        Self {
+            name: String::from("Init"),
+            mode: LayerMode::Layer,
+            split_key: 60,
+            layers: vec![Layer::default()],
+            master: MasterSettings::default(),
+        }
+    }
+}
+
+impl Performance {
+    // Layers past the second are dropped, and a file with none gets the
+    // init patch. A layer channel outside 1 to 16 is an error.
+    pub fn load(path: &Path) -> Result<Performance, SynthError> {
+        let mut performance: Performance = load_json(path)?;
+        let bad_channel = performance.layers.iter().find_map(|layer| layer.channel.filter(|c| !(1..=16).contains(c)));
+        if let Some(channel) = bad_channel {
+            let message = format!("layer channel {} is outside 1 to 16", channel);
+            return Err(SynthError::PresetFormat(serde_json::Error::custom(message)));
+        }
+        performance.layers.truncate(MAX_LAYERS);
+        if performance.layers.is_empty() {
+            performance.layers.push(Layer::default());
+        }
+        Ok(performance)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), SynthError> {
+        save_json(self, path)
+    }
+
+    // A single layer playing `preset`, for when there's no performance file.
+    pub fn from_preset(preset: Preset) -> Performance {
+        Performance {
+            name: preset.name.clone(),
+            layers: vec![Layer { preset, ..Layer::default() }],
+            ..Performance::default()
+        }
+    }
+
+    // Whether `layer` listens to `channel`, counted from zero as in the
+    // status byte.
+    pub fn listens(&self, layer: usize, channel: u8) -> bool {
+        self.layers
+            .get(layer)
+            .is_some_and(|settings| settings.channel.is_none_or(|wanted| wanted == channel + 1))
+    }
+
+    // The layer a patch or parameter change on `channel` goes to: the
+    // selected layer if it listens there, otherwise the first that does.
+    pub fn patch_layer(&self, selected: usize, channel: u8) -> Option<usize> {
+        if self.listens(selected, channel) {
+            return Some(selected);
+        }
+        (0..self.layers.len()).find(|&layer| self.listens(layer, channel))
+    }
+
+    // Whether striking `key` sounds `layer`.
+    pub fn plays(&self, layer: usize, key: u8) -> bool {
+        match self.mode {
+            LayerMode::Layer => true,
+            LayerMode::Split if key < self.split_key => layer == 0,
+            LayerMode::Split => layer == 1,
+        }
+    }
+}
+
+// A synth per layer, mixed at each layer's volume and pan. Channel messages
+// reach only the layers listening on their channel: notes go to those whose
+// key range they fall in, and patch and parameter changes to one of them,
+// preferring the selected layer. Anything without a channel, such as the
+// tempo, goes to every layer, apart from a whole new patch.
+pub struct LayeredSynth {
+    performance: Performance, // Routing and master settings only; the synths own the live presets
+    synths: Vec<Synth>,
+    gains: Vec<(f32, f32)>,
+    selected: usize,
+    scratch: Vec<f32>,
+    spent: Vec<SynthEvent>, // Event boxes done with, as Synth::take_spent
+}
+
+impl LayeredSynth {
+    pub fn new(
+        performance: Performance,
+        wavetable: Arc<Wavetable>,
+        sample: Option<Arc<Sample>>,
+        sample_rate: u32,
+    ) -> LayeredSynth {
+        let layers = &performance.layers[..performance.layers.len().min(MAX_LAYERS)];
+        let synths = layers
+            .iter()
+            .map(|layer| Synth::new(layer.preset.clone(), Arc::clone(&wavetable), sample.clone(), sample_rate))
+            .collect();
+        let gains = layers
+            .iter()
+            .map(|layer| {
+                let pan = layer.pan.clamp(-1.0, 1.0);
+                (layer.volume * (1.0 - pan).min(1.0), layer.volume * (1.0 + pan).min(1.0))
+            })
+            .collect();
+        LayeredSynth {
+            performance,
+            synths,
+            gains,
+            selected: 0,
+            scratch: vec![0.0; BLOCK_SIZE * 2],
+            spent: Vec::with_capacity(MAX_LAYERS),
+        }
+    }
+
+    pub fn master(&self) -> &MasterSettings {
+        &self.performance.master
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.synths.first().map_or(0, |synth| synth.sample_rate())
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        for synth in self.synths.iter_mut() {
+            synth.set_sample_rate(sample_rate);
+        }
+    }
+
+    pub fn handle(&mut self, event: SynthEvent) {
+        match event {
+            SynthEvent::NoteOn { channel, key, .. } => {
+                for (layer, synth) in self.synths.iter_mut().enumerate() {
+                    if self.performance.listens(layer, channel) && self.performance.plays(layer, key) {
+                        synth.handle(event.clone());
+                    }
+                }
+            }
+            // Each layer ignores note-offs for keys it isn't holding, and
+            // bends by its own preset's range.
+            SynthEvent::NoteOff { channel, .. }
+            | SynthEvent::PitchBend { channel, .. }
+            | SynthEvent::Pressure { channel, .. }
+            | SynthEvent::Timbre { channel, .. } => {
+                for (layer, synth) in self.synths.iter_mut().enumerate() {
+                    if self.performance.listens(layer, channel) {
+                        synth.handle(event.clone());
+                    }
+                }
+            }
+            SynthEvent::OnChannel(channel, mut event) => {
+                let inner = std::mem::replace(&mut *event, SynthEvent::AllNotesOff);
+                self.retire(SynthEvent::OnChannel(channel, event));
+                match inner {
+                    SynthEvent::Control(..) | SynthEvent::Preset(_) => {
+                        let layer = self.performance.patch_layer(self.selected, channel);
+                        self.handle_on(layer, inner);
+                    }
+                    _ => {
+                        for (layer, synth) in self.synths.iter_mut().enumerate() {
+                            if self.performance.listens(layer, channel) {
+                                synth.handle(inner.clone());
+                            }
+                        }
+                    }
+                }
+            }
+            // A sysex restore, which has no channel, goes to the selected layer.
+            SynthEvent::Preset(_) => self.handle_on(Some(self.selected), event),
+            SynthEvent::SelectLayer(layer) => {
+                if layer < self.synths.len() {
+                    self.selected = layer;
+                }
+            }
+            _ => {
+                for synth in self.synths.iter_mut() {
+                    synth.handle(event.clone());
+                }
+            }
+        }
+    }
+
+    // Sends `event` to `layer`'s synth, or keeps it for take_spent if there
+    // isn't one.
+    fn handle_on(&mut self, layer: Option<usize>, event: SynthEvent) {
+        match layer.and_then(|layer| self.synths.get_mut(layer)) {
+            Some(synth) => synth.handle(event),
+            None => self.retire(event),
+        }
+    }
+
+    fn retire(&mut self, event: SynthEvent) {
+        if self.spent.len() < self.spent.capacity() {
+            self.spent.push(event);
+        }
+    }
+
+    // Presets and event boxes every layer is done with since the last call,
+    // for dropping off the audio thread.
+    pub fn take_spent(&mut self) -> impl Iterator<Item = SynthEvent> + '_ {
+        self.spent.drain(..).chain(self.synths.iter_mut().flat_map(|synth| synth.take_spent()))
+    }
+
+    // Voice render time across every layer, as Synth::take_voice_time.
+    pub fn take_voice_time(&mut self) -> (Duration, u32) {
+        self.synths.iter_mut().map(|synth| synth.take_voice_time()).fold(
+            (Duration::ZERO, 0),
+            |(total_time, total_renders), (time, renders)| (total_time + time, total_renders + renders),
+        )
+    }
+
+    pub fn is_silent(&self) -> bool {
+        self.synths.iter().all(|synth| synth.is_silent())
+    }
+
+    // Adds every layer into interleaved stereo `buffer`.
+    pub fn render(&mut self, buffer: &mut [f32]) {
+        if let [synth] = &mut self.synths[..] {
+            if self.gains[0] == (1.0, 1.0) {
+                synth.render(buffer);
+                return;
+            }
+        }
+        // A block at a time through the scratch buffer, so rendering never
+        // allocates whatever the buffer size.
+        for chunk in buffer.chunks_mut(self.scratch.len()) {
+            let scratch = &mut self.scratch[..chunk.len()];
+            for (synth, (left_gain, right_gain)) in self.synths.iter_mut().zip(self.gains.iter()) {
+                scratch.fill(0.0);
+                synth.render(scratch);
+                for (output, frame) in chunk.chunks_exact_mut(2).zip(scratch.chunks_exact(2)) {
+                    output[0] += frame[0] * left_gain;
+                    output[1] += frame[1] * right_gain;
+                }
+            }
+        }
+    }
+}
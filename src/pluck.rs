@@ -0,0 +1,97 @@
+use crate::lfo;
+
+const MIN_FREQUENCY: f32 = 8.0; // Hz, just below MIDI key 0
+const LOOP_GAIN: f32 = 0.999; // Keeps undamped strings from ringing forever
+
+// A Karplus-Strong string: a burst of noise circulating in a delay line one
+// period long, losing its highs on every trip round the loop. The line is
+// handed in by whoever owns the voice, so voices clone from the template
+// without allocating or copying one, and only strings carry a line at all.
+#[derive(Clone)]
+pub struct Pluck {
+    delay: Vec<f32>,
+    write: usize,
+    previous: f32,
+    damping: f32,
+    brightness: f32,
+}
+
+// Samples of delay line a string needs at `sample_rate` to reach down to
+// the lowest MIDI note.
+pub fn line_length(sample_rate: u32) -> usize {
+    (sample_rate as f32 / MIN_FREQUENCY).ceil() as usize + 2
+}
+
+impl Pluck {
+    // `damping` (0.0 to 1.0) is how quickly the highs die away, and
+    // `brightness` (0.0 to 1.0) how much of them the pluck starts with.
+    pub fn new(damping: f32, brightness: f32) -> Pluck {
+        let mut pluck = Pluck {
+            delay: Vec::new(),
+            write: 0,
+            previous: 0.0,
+            damping: 0.0,
+            brightness: 0.0,
+        };
+        pluck.set_tone(damping, brightness);
+        pluck
+    }
+
+    pub fn set_tone(&mut self, damping: f32, brightness: f32) {
+        self.damping = damping.clamp(0.0, 1.0);
+        self.brightness = brightness.clamp(0.0, 1.0);
+    }
+
+    // Takes `line` as the delay line and hands back the one it had, empty
+    // if none. A string without a line is silent.
+    pub fn swap_line(&mut self, line: &mut Vec<f32>) {
+        std::mem::swap(&mut self.delay, line);
+        self.write = 0;
+    }
+
+    // Regrows a line in use to reach the lowest note at `sample_rate`.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        if !self.delay.is_empty() {
+            self.delay.resize(line_length(sample_rate), 0.0);
+            self.write %= self.delay.len();
+        }
+    }
+
+    // Fills one period of the line with noise, low-passed by the brightness.
+    pub fn excite(&mut self, period: f32, seed: &mut u32) {
+        let size = self.delay.len();
+        let length = (period.clamp(0.0, size as f32).ceil() as usize + 1).min(size);
+        let coefficient = self.brightness.max(0.01);
+        let mut smoothed = 0.0;
+        self.delay.fill(0.0);
+        for sample in self.delay[..length].iter_mut() {
+            smoothed += (lfo::random(seed) - smoothed) * coefficient;
+            *sample = smoothed;
+        }
+        self.write = if size > 0 { length % size } else { 0 };
+        self.previous = 0.0;
+    }
+
+    // Next sample of a string `period` samples long, which may change as the
+    // note bends.
+    pub fn next_sample(&mut self, period: f32) -> f32 {
+        let size = self.delay.len();
+        if size < 4 {
+            return 0.0;
+        }
+        // The two-point average in the loop delays by `weight` samples, so
+        // read that much sooner to stay in tune.
+        let weight = self.damping * 0.5;
+        let period = (period - weight).clamp(2.0, (size - 2) as f32);
+        let read = self.write as f32 + size as f32 - period;
+        let index = read as usize;
+        let current = self.delay[index % size];
+        let next = self.delay[(index + 1) % size];
+        let delayed = current + (next - current) * read.fract();
+
+        self.delay[self.write] = LOOP_GAIN * (delayed + (self.previous - delayed) * weight);
+        self.previous = delayed;
+        self.write = (self.write + 1) % size;
+        delayed
+    }
+}
@@ -0,0 +1,464 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::arp::ArpPattern;
+use crate::envelope::{EnvelopeMode, ADSR};
+use crate::error::SynthError;
+use crate::json::{load_json, save_json};
+use crate::lfo::{self, LfoShape, LfoSync, LfoTarget, LFO};
+use crate::midi::VelocityCurve;
+use crate::mod_matrix::ModSlot;
+use crate::params::Parameter;
+use crate::pluck;
+use crate::synth::MAX_VOICES;
+use crate::wavetable::Wavetable;
+use crate::wavetable_oscillator::{GlideCurve, SubShape, WaveType, WavetableOscillator};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LfoSettings {
+    pub shape: LfoShape,
+    pub rate: f32,
+    pub depth: f32,
+    pub target: LfoTarget,
+    pub sync: Option<LfoSync>, // Overrides `rate` with a note length at the preset's tempo
+    #[serde(default)]
+    pub free_run: bool,
+    #[serde(default)]
+    pub fade_in: f32,
+}
+
+impl LfoSettings {
+    fn lfo(&self, sample_rate: u32, tempo: f32) -> LFO {
+        let rate = self.sync.map_or(self.rate, |sync| sync.rate(tempo));
+        let mut lfo = LFO::new(sample_rate, self.shape, rate, self.depth, self.target);
+        lfo.set_retrigger(!self.free_run);
+        lfo.set_fade_in(self.fade_in);
+        lfo
+    }
+}
+
+// Steps through the held keys instead of playing them together, one note
+// of `rate` at the preset's tempo per step.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ArpSettings {
+    pub pattern: ArpPattern,
+    pub octaves: u8, // 1 to 4
+    pub rate: LfoSync,
+    pub gate: f32, // Fraction of each step the note sounds for, 0.05 to 1.0
+}
+
+// How successive notes are spread across the stereo field.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SpreadMode {
+    Alternate, // Left, right, left...
+    Random,
+}
+
+// What striking a key does while its last note is still sounding.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Retrigger {
+    Stack,       // Start another voice alongside it
+    Always,      // Restart its envelope from zero
+    FromCurrent, // Restart its attack from the current level
+    Legato,      // Carry on if held, otherwise pick up from the current level
+    Cut,         // Fade it out at once and start a fresh voice
+}
+
+// Whether notes play chords or a single line.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum VoiceMode {
+    Poly,
+    Mono,   // One voice, restarting its envelope on every note
+    Legato, // One voice, restarting its envelope only when no other key was held
+}
+
+// Which held key a mono voice plays.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum NotePriority {
+    Last,
+    Low,
+    High,
+}
+
+// Which sounding voice a new note takes over once `polyphony` are playing.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum VoiceSteal {
+    Oldest,   // The longest-playing, preferring ones already released
+    Quietest, // The one lowest in its envelope
+    SameNote, // One already on the struck key, otherwise the oldest
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum GlideMode {
+    ConstantTime, // Every glide takes glide_time
+    ConstantRate, // glide_time per octave travelled
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preset {
+    pub name: String,
+    pub author: String,
+    pub category: String, // "Bass", "Lead", "Pad"...
+    pub tags: Vec<String>,
+    pub volume: f32,
+    pub pan: f32,
+    pub voice_spread: f32, // 0.0 to 1.0 of the way from `pan` to either side
+    pub spread_mode: SpreadMode,
+    pub wave_type: WaveType,
+    pub wave_level: f32,
+    pub drift: f32, // Cents of slow random pitch wander per oscillator
+    pub wavetable_position: f32,
+    pub pluck_damping: f32,    // 0.0 to 1.0; higher loses the highs sooner
+    pub pluck_brightness: f32, // 0.0 to 1.0 of the pluck's noise kept bright
+    pub fm_ratio: f32,         // Modulator frequency over the note's, 0.5 to 16
+    pub fm_index: f32,         // Radians of phase deviation
+    pub fm_env_amount: f32,    // Extra index at the start of each note
+    pub fm_env_decay: f32,
+    pub octave: i32,    // -3 to 3
+    pub semitone: i32,  // -12 to 12
+    pub fine_tune: f32, // Cents
+    pub pitch_bend_range: f32, // Semitones either way at full bend
+    pub attack: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    pub release: f32,
+    pub envelope_mode: EnvelopeMode,
+    pub retrigger: Retrigger,
+    pub polyphony: usize, // Voices sounding at once, 1 to MAX_VOICES
+    pub voice_steal: VoiceSteal,
+    pub voice_mode: VoiceMode,
+    pub note_priority: NotePriority,
+    pub velocity_curve: VelocityCurve,
+    pub velocity_to_attack: f32, // -1.0 to 1.0; positive makes harder notes attack faster
+    pub unison_voices: usize,
+    pub unison_detune: f32,
+    pub unison_spread: f32,
+    pub random_phase: bool,
+    pub start_phase: f32, // 0.0 to 1.0 of a cycle
+    pub free_run: bool,   // Keep the oscillator's phase running between notes
+    pub sub_shape: SubShape,
+    pub sub_octave: u8, // 1 or 2 octaves below the note
+    pub sub_level: f32,
+    pub sample_level: f32, // Of the sample layer, when one is loaded
+    pub pitch_env_amount: f32, // Semitones
+    pub pitch_env_decay: f32,
+    pub glide_time: f32, // Zero turns portamento off
+    pub glide_mode: GlideMode,
+    pub glide_curve: GlideCurve,
+    pub tempo: f32, // Beats per minute, for synced LFOs
+    pub lfo: Option<LfoSettings>,
+    pub lfo2: Option<LfoSettings>,
+    pub mod_matrix: Vec<ModSlot>,
+    pub arp: Option<ArpSettings>,
+}
+
+impl Default for Preset {
+    fn default() -> Self {
+        Self {
+            name: String::from("Init"),
+            author: String::new(),
+            category: String::new(),
+            tags: Vec::new(),
+            volume: 0.5,
+            pan: 0.0,
+            voice_spread: 0.0,
+            spread_mode: SpreadMode::Alternate,
+            wave_type: WaveType::Wavetable,
+            wave_level: 1.0,
+            drift: 0.0,
+            wavetable_position: 0.5,
+            pluck_damping: 0.5,
+            pluck_brightness: 1.0,
+            fm_ratio: 1.0,
+            fm_index: 1.0,
+            fm_env_amount: 0.0,
+            fm_env_decay: 0.5,
+            octave: 0,
+            semitone: 0,
+            fine_tune: 0.0,
+            pitch_bend_range: 2.0,
+            attack: 0.01,
+            decay: 0.1,
+            sustain: 0.7,
+            release: 0.2,
+            envelope_mode: EnvelopeMode::Adsr,
+            retrigger: Retrigger::Stack,
+            polyphony: MAX_VOICES,
+            voice_steal: VoiceSteal::Oldest,
+            voice_mode: VoiceMode::Poly,
+            note_priority: NotePriority::Last,
+            velocity_curve: VelocityCurve::Linear,
+            velocity_to_attack: 0.0,
+            unison_voices: 1,
+            unison_detune: 0.0,
+            unison_spread: 0.0,
+            random_phase: false,
+            start_phase: 0.0,
+            free_run: false,
+            sub_shape: SubShape::Sine,
+            sub_octave: 1,
+            sub_level: 0.0,
+            sample_level: 1.0,
+            pitch_env_amount: 0.0,
+            pitch_env_decay: 0.05,
+            glide_time: 0.0,
+            glide_mode: GlideMode::ConstantTime,
+            glide_curve: GlideCurve::Linear,
+            tempo: 120.0,
+            lfo: None,
+            lfo2: None,
+            mod_matrix: Vec::new(),
+            arp: None,
+        }
+    }
+}
+
+impl Preset {
+    pub fn load(path: &Path) -> Result<Preset, SynthError> {
+        load_json(path)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), SynthError> {
+        save_json(self, path)
+    }
+
+    // Moves each randomised parameter `amount` (0.0 to 1.0) of the way
+    // towards a random value, so 1.0 rolls a new patch and 0.1 mutates this
+    // one by 10%.
+    pub fn randomize(&mut self, mut seed: u32, amount: f32) {
+        let amount = amount.clamp(0.0, 1.0);
+        for parameter in Parameter::RANDOMIZED {
+            let (min, max) = parameter.random_range();
+            let target = min + (lfo::random(&mut seed) * 0.5 + 0.5) * (max - min);
+            let current = parameter.get(self);
+            parameter.set(self, current + (target - current) * amount);
+        }
+    }
+
+    pub fn oscillator(&self, sample_rate: u32, wavetable: Arc<Wavetable>) -> WavetableOscillator {
+        let mut adsr = ADSR::new(self.attack, self.decay, self.sustain, self.release);
+        adsr.set_mode(self.envelope_mode);
+        let mut oscillator = WavetableOscillator::new(sample_rate, wavetable, self.volume, adsr);
+        oscillator.set_wave_type(self.wave_type);
+        oscillator.set_wave_level(self.wave_level);
+        oscillator.set_drift(self.drift);
+        oscillator.set_position(self.wavetable_position);
+        oscillator.set_pluck(self.pluck_damping, self.pluck_brightness);
+        oscillator.set_fm(self.fm_ratio, self.fm_index, self.fm_env_amount, self.fm_env_decay);
+        oscillator.set_pan(self.pan);
+        oscillator.set_unison(self.unison_voices, self.unison_detune, self.unison_spread);
+        oscillator.set_random_phase(self.random_phase);
+        oscillator.set_start_phase(self.start_phase);
+        oscillator.set_free_run(self.free_run);
+        oscillator.set_sub(self.sub_shape, self.sub_octave, self.sub_level);
+        oscillator.set_sample_level(self.sample_level);
+        oscillator.set_pitch_envelope(self.pitch_env_amount, self.pitch_env_decay);
+        oscillator.set_mod_matrix(&self.mod_matrix);
+        self.set_lfos(&mut oscillator);
+        oscillator
+    }
+
+    // Brings an oscillator built by `Preset::oscillator` up to date after
+    // `parameter` changes, without rebuilding it. Parameters that only take
+    // effect as a note starts, such as fine tune and glide, need nothing.
+    pub fn apply(&self, parameter: Parameter, oscillator: &mut WavetableOscillator) {
+        match parameter {
+            Parameter::Volume => oscillator.set_volume(self.volume),
+            Parameter::Pan => oscillator.set_pan(self.pan),
+            Parameter::WaveLevel => oscillator.set_wave_level(self.wave_level),
+            Parameter::WavetablePosition => oscillator.set_position(self.wavetable_position),
+            Parameter::PluckDamping | Parameter::PluckBrightness => {
+                oscillator.set_pluck(self.pluck_damping, self.pluck_brightness)
+            }
+            Parameter::FmRatio | Parameter::FmIndex | Parameter::FmEnvAmount | Parameter::FmEnvDecay => {
+                oscillator.set_fm(self.fm_ratio, self.fm_index, self.fm_env_amount, self.fm_env_decay)
+            }
+            Parameter::Drift => oscillator.set_drift(self.drift),
+            Parameter::Attack => oscillator.adsr.set_attack(self.attack),
+            Parameter::Decay => oscillator.adsr.set_decay(self.decay),
+            Parameter::Sustain => oscillator.adsr.set_sustain(self.sustain),
+            Parameter::Release => oscillator.adsr.set_release(self.release),
+            Parameter::UnisonDetune | Parameter::UnisonSpread => {
+                oscillator.set_unison(self.unison_voices, self.unison_detune, self.unison_spread)
+            }
+            Parameter::SubLevel => oscillator.set_sub(self.sub_shape, self.sub_octave, self.sub_level),
+            Parameter::SampleLevel => oscillator.set_sample_level(self.sample_level),
+            Parameter::PitchEnvAmount | Parameter::PitchEnvDecay => {
+                oscillator.set_pitch_envelope(self.pitch_env_amount, self.pitch_env_decay)
+            }
+            Parameter::LfoRate | Parameter::LfoDepth | Parameter::Tempo => self.set_lfos(oscillator),
+            Parameter::VoiceSpread
+            | Parameter::FineTune
+            | Parameter::PitchBendRange
+            | Parameter::GlideTime
+            | Parameter::Polyphony => (),
+        }
+    }
+
+    fn set_lfos(&self, oscillator: &mut WavetableOscillator) {
+        let sample_rate = oscillator.sample_rate();
+        oscillator.set_lfo(self.lfo.as_ref().map(|lfo| lfo.lfo(sample_rate, self.tempo)));
+        oscillator.set_lfo2(self.lfo2.as_ref().map(|lfo| lfo.lfo(sample_rate, self.tempo)));
+    }
+
+    // Starts a note `time` seconds into the session from a `template` built by
+    // `Preset::oscillator`, applying the preset's velocity response. `seed`
+    // scatters random unison phases. A string gets a new delay line.
+    pub fn voice(
+        &self,
+        template: &WavetableOscillator,
+        frequency: f32,
+        velocity: u8,
+        time: f64,
+        seed: u32,
+    ) -> WavetableOscillator {
+        let mut voice = template.clone();
+        if template.wave_type() == WaveType::Pluck {
+            voice.swap_pluck_line(&mut vec![0.0; pluck::line_length(template.sample_rate())]);
+        }
+        self.start(&mut voice, frequency, velocity, time, seed);
+        voice
+    }
+
+    // As `voice`, for a clone of the template that already has any delay
+    // line it needs, so starting it never allocates.
+    pub fn start(&self, voice: &mut WavetableOscillator, frequency: f32, velocity: u8, time: f64, seed: u32) {
+        let velocity = self.velocity_curve.apply(velocity);
+        voice.set_frequency(frequency * self.transpose());
+        voice.set_velocity(velocity);
+        if self.voice_spread > 0.0 {
+            let offset = match self.spread_mode {
+                SpreadMode::Alternate if seed % 2 == 0 => -1.0,
+                SpreadMode::Alternate => 1.0,
+                SpreadMode::Random => {
+                    let mut spread_seed = seed.wrapping_add(1);
+                    lfo::random(&mut spread_seed)
+                }
+            };
+            voice.set_pan(self.pan + offset * self.voice_spread);
+        }
+        voice.reset_phase(time, seed);
+        voice.start_lfos(time);
+        voice.adsr.set_attack(self.attack * (1.0 - self.velocity_to_attack * velocity));
+        voice.adsr.start(0.0);
+    }
+
+    // Replays a voice whose key is struck again while it's still sounding,
+    // following the preset's retrigger mode. `held` is whether the key was
+    // still down.
+    pub fn retrigger(&self, voice: &mut WavetableOscillator, frequency: f32, velocity: u8, held: bool) {
+        voice.set_frequency(frequency * self.transpose());
+        voice.set_velocity(self.velocity_curve.apply(velocity));
+        match self.retrigger {
+            Retrigger::Stack | Retrigger::Legato if held => (),
+            Retrigger::Always => voice.restart_envelope(false),
+            _ => voice.restart_envelope(true),
+        }
+    }
+
+    // Moves a sounding voice's tempo-synced LFOs to the preset's tempo.
+    pub fn sync_lfos(&self, voice: &mut WavetableOscillator) {
+        let synced = |lfo: &Option<LfoSettings>| {
+            lfo.as_ref().and_then(|lfo| lfo.sync).map(|sync| sync.rate(self.tempo))
+        };
+        voice.set_lfo_rates(synced(&self.lfo), synced(&self.lfo2));
+    }
+
+    // Moves a sounding voice to its key's new frequency when the tuning
+    // changes, keeping its place in the envelope.
+    pub fn retune(&self, voice: &mut WavetableOscillator, frequency: f32) {
+        voice.set_frequency(frequency * self.transpose());
+    }
+
+    fn transpose(&self) -> f32 {
+        let semitones = (self.octave.clamp(-3, 3) * 12 + self.semitone.clamp(-12, 12)) as f32;
+        2.0_f32.powf((semitones + self.fine_tune / 100.0) / 12.0)
+    }
+
+    // Slides a new voice in from the previous note's frequency, if the preset
+    // has portamento on.
+    pub fn glide(&self, voice: &mut WavetableOscillator, from: f32, to: f32) {
+        if self.glide_time <= 0.0 {
+            return;
+        }
+        let semitones = 12.0 * (from / to).log2();
+        let duration = match self.glide_mode {
+            GlideMode::ConstantTime => self.glide_time,
+            GlideMode::ConstantRate => self.glide_time * semitones.abs() / 12.0,
+        };
+        voice.set_glide(semitones, duration, self.glide_curve);
+    }
+}
+
+// Lists the `.json` presets in `dir`, sorted by file name.
+pub fn scan_presets(dir: &Path) -> Result<Vec<PathBuf>, SynthError> {
+    let mut presets = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|extension| extension == "json") {
+            presets.push(path);
+        }
+    }
+    presets.sort();
+    Ok(presets)
+}
+
+// Many presets in one file, for browsing by category and switching between
+// with program changes.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PresetBank {
+    pub name: String,
+    pub presets: Vec<Preset>,
+}
+
+impl PresetBank {
+    pub fn load(path: &Path) -> Result<PresetBank, SynthError> {
+        load_json(path)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), SynthError> {
+        save_json(self, path)
+    }
+
+    // Gathers every preset in `dir` into a bank named after the directory.
+    pub fn from_dir(dir: &Path) -> Result<PresetBank, SynthError> {
+        let presets = scan_presets(dir)?
+            .iter()
+            .map(|path| Preset::load(path))
+            .collect::<Result<_, _>>()?;
+        let name = dir.file_name().map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+        Ok(PresetBank { name, presets })
+    }
+
+    // The distinct categories in the bank, in the order they first appear.
+    pub fn categories(&self) -> Vec<&str> {
+        let mut categories = Vec::new();
+        for preset in &self.presets {
+            if !preset.category.is_empty() && !categories.contains(&preset.category.as_str()) {
+                categories.push(preset.category.as_str());
+            }
+        }
+        categories
+    }
+
+    // Presets in `category` (any if None) whose name, author or tags contain
+    // `text`, ignoring case, with their program numbers.
+    pub fn search(&self, category: Option<&str>, text: &str) -> Vec<(usize, &Preset)> {
+        let text = text.to_lowercase();
+        self.presets
+            .iter()
+            .enumerate()
+            .filter(|(_, preset)| category.is_none_or(|category| preset.category.eq_ignore_ascii_case(category)))
+            .filter(|(_, preset)| {
+                preset.name.to_lowercase().contains(&text)
+                    || preset.author.to_lowercase().contains(&text)
+                    || preset.tags.iter().any(|tag| tag.to_lowercase().contains(&text))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,238 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+use midly::{MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
+
+use crate::error::SynthError;
+use crate::lfo;
+use crate::preset::Preset;
+use crate::sampler::Sample;
+use crate::synth::Synth;
+use crate::tuning::Tuning;
+use crate::wavetable::Wavetable;
+
+const TAIL_BLOCK: usize = 1024; // Frames rendered at a time while voices release
+const NORMALIZE_PEAK: f32 = 0.989; // -0.1 dBFS, leaving room for dither
+const SILENCE: f32 = 0.0001; // -80 dBFS; quieter frames are trimmed from the ends
+const INT24_MAX: f32 = 8_388_607.0;
+const DITHER_SEED: u32 = 1; // Fixed so the same render always writes the same file
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BitDepth {
+    Int16,
+    Int24,
+    Float32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MidiEvent {
+    pub time: f64, // Seconds from the start of the render
+    pub channel: u8,
+    pub kind: EventKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EventKind {
+    Note { key: u8, velocity: u8 }, // Velocity zero for note-offs
+    PitchBend(f32),                 // -1.0 to 1.0
+    Pressure { key: Option<u8>, pressure: f32 }, // 0.0 to 1.0; a key for polyphonic aftertouch
+}
+
+// Flattens every track of a Standard MIDI File into note, pitch bend and
+// pressure events in time order, following tempo changes for metrical files.
+pub fn read_midi_file(path: &Path) -> Result<Vec<MidiEvent>, SynthError> {
+    let bytes = fs::read(path)?;
+    let smf = Smf::parse(&bytes)?;
+
+    let mut events = Vec::new();
+    for track in &smf.tracks {
+        let mut tick: u64 = 0;
+        for event in track {
+            tick += event.delta.as_int() as u64;
+            events.push((tick, event.kind));
+        }
+    }
+    // Stable, so simultaneous events keep their order within a track.
+    events.sort_by_key(|(tick, _)| *tick);
+
+    let ticks_per_beat = match smf.header.timing {
+        Timing::Metrical(ticks) => Some(ticks.as_int() as f64),
+        Timing::Timecode(..) => None,
+    };
+    let mut seconds_per_tick = match smf.header.timing {
+        Timing::Metrical(ticks) => 0.5 / ticks.as_int() as f64, // 120 BPM until told otherwise
+        Timing::Timecode(fps, subframes) => 1.0 / (fps.as_f32() as f64 * subframes as f64),
+    };
+
+    let mut midi_events = Vec::new();
+    let mut time = 0.0;
+    let mut last_tick = 0;
+    for (tick, kind) in events {
+        time += (tick - last_tick) as f64 * seconds_per_tick;
+        last_tick = tick;
+
+        match kind {
+            TrackEventKind::Meta(MetaMessage::Tempo(micros_per_beat)) => {
+                if let Some(ticks_per_beat) = ticks_per_beat {
+                    seconds_per_tick = micros_per_beat.as_int() as f64 / 1_000_000.0 / ticks_per_beat;
+                }
+            }
+            TrackEventKind::Midi { channel, message } => {
+                let kind = match message {
+                    MidiMessage::NoteOn { key, vel } => EventKind::Note { key: key.as_int(), velocity: vel.as_int() },
+                    MidiMessage::NoteOff { key, .. } => EventKind::Note { key: key.as_int(), velocity: 0 },
+                    MidiMessage::PitchBend { bend } => EventKind::PitchBend(bend.as_int() as f32 / 8192.0),
+                    MidiMessage::Aftertouch { key, vel } => {
+                        EventKind::Pressure { key: Some(key.as_int()), pressure: vel.as_int() as f32 / 127.0 }
+                    }
+                    MidiMessage::ChannelAftertouch { vel } => {
+                        EventKind::Pressure { key: None, pressure: vel.as_int() as f32 / 127.0 }
+                    }
+                    _ => continue,
+                };
+                midi_events.push(MidiEvent {
+                    time,
+                    channel: channel.as_int(),
+                    kind,
+                });
+            }
+            _ => (),
+        }
+    }
+
+    Ok(midi_events)
+}
+
+// Plays `events` through the preset and returns interleaved stereo samples,
+// running on until every voice's release has finished.
+pub fn render_events(
+    events: &[MidiEvent],
+    preset: &Preset,
+    tuning: &Tuning,
+    wavetable: Arc<Wavetable>,
+    sample: Option<Arc<Sample>>,
+    sample_rate: u32,
+) -> Vec<f32> {
+    let mut synth = Synth::new(preset.clone(), wavetable, sample, sample_rate);
+    let mut output = Vec::new();
+
+    for event in events {
+        let event_frame = (event.time * sample_rate as f64).round() as usize;
+        let frames = event_frame.saturating_sub(output.len() / 2);
+        render_frames(&mut synth, &mut output, frames);
+
+        match event.kind {
+            EventKind::Note { key, velocity: 0 } => synth.note_off(event.channel, key),
+            EventKind::Note { key, velocity } => {
+                if let Some(frequency) = tuning.frequency(key) {
+                    synth.note_on(event.channel, key, frequency, velocity);
+                }
+            }
+            EventKind::PitchBend(bend) => synth.pitch_bend(event.channel, bend),
+            EventKind::Pressure { key, pressure } => synth.set_pressure(event.channel, key, pressure),
+        }
+    }
+
+    // Anything the file left hanging is released at the end.
+    synth.release_all();
+    while !synth.is_silent() {
+        render_frames(&mut synth, &mut output, TAIL_BLOCK);
+    }
+
+    output
+}
+
+fn render_frames(synth: &mut Synth, output: &mut Vec<f32>, frames: usize) {
+    let start = output.len();
+    output.resize(start + frames * 2, 0.0);
+    synth.render(&mut output[start..]);
+}
+
+// Scales interleaved samples so the loudest peak sits just under full scale.
+pub fn normalize(samples: &mut [f32]) {
+    let peak = samples.iter().fold(0.0_f32, |peak, sample| peak.max(sample.abs()));
+    if peak > 0.0 {
+        let gain = NORMALIZE_PEAK / peak;
+        samples.iter_mut().for_each(|sample| *sample *= gain);
+    }
+}
+
+// Drops the silent stereo frames before the first sound and after the last.
+pub fn trim_silence(samples: &mut Vec<f32>) {
+    let audible = |frame: &[f32]| frame.iter().any(|sample| sample.abs() > SILENCE);
+    let Some(first) = samples.chunks_exact(2).position(audible) else {
+        samples.clear();
+        return;
+    };
+    let last = samples.chunks_exact(2).rposition(audible).unwrap_or(first);
+    samples.truncate((last + 1) * 2);
+    samples.drain(..first * 2);
+}
+
+// With `dither`, 16-bit output gets triangular (TPDF) dither of one LSB
+// either way before rounding, trading truncation distortion for a steady
+// noise floor. The other depths have the resolution not to need it.
+pub fn write_wav(
+    path: &Path,
+    samples: &[f32],
+    sample_rate: u32,
+    bit_depth: BitDepth,
+    dither: bool,
+) -> Result<(), SynthError> {
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate,
+        bits_per_sample: match bit_depth {
+            BitDepth::Int16 => 16,
+            BitDepth::Int24 => 24,
+            BitDepth::Float32 => 32,
+        },
+        sample_format: match bit_depth {
+            BitDepth::Int16 | BitDepth::Int24 => SampleFormat::Int,
+            BitDepth::Float32 => SampleFormat::Float,
+        },
+    };
+
+    let mut writer = WavWriter::create(path, spec)?;
+    let mut seed = DITHER_SEED;
+    for &sample in samples {
+        match bit_depth {
+            BitDepth::Int16 => {
+                let mut value = sample.clamp(-1.0, 1.0) * i16::MAX as f32;
+                if dither {
+                    value += (lfo::random(&mut seed) + lfo::random(&mut seed)) * 0.5;
+                }
+                writer.write_sample(value.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16)?
+            }
+            BitDepth::Int24 => writer.write_sample((sample.clamp(-1.0, 1.0) * INT24_MAX).round() as i32)?,
+            BitDepth::Float32 => writer.write_sample(sample)?,
+        }
+    }
+    writer.finalize()?;
+    Ok(())
+}
+
+// Reads a WAV file of any sample format, mixed down to mono and scaled to
+// -1.0 to 1.0, with its sample rate.
+pub fn read_wav(path: &Path) -> Result<(Vec<f32>, u32), SynthError> {
+    let mut reader = WavReader::open(path)?;
+    let spec = reader.spec();
+    let samples: Vec<f32> = match spec.sample_format {
+        SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+        SampleFormat::Int => {
+            let scale = 1.0 / (1_i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|sample| sample.map(|sample| sample as f32 * scale))
+                .collect::<Result<_, _>>()?
+        }
+    };
+    let channels = spec.channels as usize;
+    let mono = samples
+        .chunks_exact(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+    Ok((mono, spec.sample_rate))
+}
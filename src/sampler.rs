@@ -0,0 +1,62 @@
+use std::path::Path;
+
+use crate::error::SynthError;
+use crate::render;
+use crate::wavetable::TABLE_SIZE;
+
+// A recording played back across the keyboard, sounding at its own pitch on
+// `root_key` and shifted by resampling elsewhere. With loop points it
+// sustains by cycling between them; without, it plays once and falls silent.
+pub struct Sample {
+    data: Vec<f32>,
+    sample_rate: u32,
+    root_frequency: f32,
+    loop_points: Option<(usize, usize)>,
+}
+
+impl Sample {
+    // Loop points are in frames of the file and are dropped if they don't
+    // fit inside it.
+    pub fn load(path: &Path, root_key: u8, loop_points: Option<(usize, usize)>) -> Result<Sample, SynthError> {
+        let (data, sample_rate) = render::read_wav(path)?;
+        let loop_points = loop_points.filter(|(start, end)| start < end && *end <= data.len());
+        Ok(Sample {
+            data,
+            sample_rate,
+            root_frequency: 440.0 * 2.0_f32.powf((root_key as f32 - 69.0) / 12.0),
+            loop_points,
+        })
+    }
+
+    // Frames of the recording to step through per output sample, for an
+    // oscillator advancing `index_increment` through a wavetable cycle.
+    pub fn increment(&self, index_increment: f32) -> f64 {
+        index_increment as f64 * self.sample_rate as f64 / (TABLE_SIZE as f64 * self.root_frequency as f64)
+    }
+
+    // Interpolated value at `position` frames in; silence past the end.
+    pub fn value(&self, position: f64) -> f32 {
+        let frame = position as usize;
+        let Some(&current) = self.data.get(frame) else {
+            return 0.0;
+        };
+        let next = match self.loop_points {
+            Some((start, end)) if frame + 1 == end => self.data[start],
+            _ => self.data.get(frame + 1).copied().unwrap_or(0.0),
+        };
+        let weight = (position - frame as f64) as f32;
+        current + (next - current) * weight
+    }
+
+    // Moves `position` on by `increment`, wrapping back into the loop once it
+    // passes the loop end.
+    pub fn advance(&self, position: f64, increment: f64) -> f64 {
+        let position = position + increment;
+        match self.loop_points {
+            Some((start, end)) if position >= end as f64 => {
+                start as f64 + (position - start as f64) % (end - start) as f64
+            }
+            _ => position,
+        }
+    }
+}
@@ -0,0 +1,860 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::arp::{ArpNote, Arpeggiator};
+use crate::master::{MasterBus, MasterMeter};
+use crate::params::Parameter;
+use crate::performance::LayeredSynth;
+use crate::pluck;
+use crate::preset::{NotePriority, Preset, Retrigger, VoiceMode, VoiceSteal};
+use crate::sampler::Sample;
+use crate::tuning::Tuning;
+use crate::wavetable::Wavetable;
+use crate::wavetable_oscillator::{WaveType, WavetableOscillator};
+
+pub const MAX_VOICES: usize = 16;
+const DYING_VOICES: usize = 4; // Stolen voices that can fade out at once
+const PANIC_FADE: f32 = 0.005; // Seconds, short enough to stop at once without a click
+pub const BLOCK_SIZE: usize = 64; // Frames rendered between checks for new events
+const SOFT_PEDAL: f32 = 0.6; // Velocity scale while the soft pedal is down
+const LOAD_SMOOTHING: f32 = 0.05; // Weight of each block in the load average
+const HELD_KEYS: usize = 32; // Keys a mono voice remembers to fall back to
+const SPENT_EVENTS: usize = 8; // Spent presets and event boxes kept per block for dropping elsewhere
+
+#[derive(Clone)]
+pub enum SynthEvent {
+    NoteOn { channel: u8, key: u8, frequency: f32, velocity: u8 },
+    NoteOff { channel: u8, key: u8 },
+    PitchBend { channel: u8, bend: f32 }, // -1.0 to 1.0 of the preset's bend range
+    Retune { key: u8, frequency: f32 },   // A key's new frequency, for the notes already sounding on it
+    Pressure { channel: u8, key: Option<u8>, pressure: f32 }, // 0.0 to 1.0; a key for polyphonic aftertouch
+    Timbre { channel: u8, timbre: f32 }, // CC 74, 0.0 to 1.0
+    Mpe(Option<f32>), // MPE on with member channels bending this many semitones, or off
+    Control(Parameter, f32),
+    ModWheel(f32),
+    Preset(Box<Preset>),
+    Latch(bool),
+    Sustain(bool),
+    Sostenuto(bool),
+    SoftPedal(bool),
+    AllNotesOff,
+    AllSoundOff,
+    SelectLayer(usize), // Which layer of a performance patch changes go to
+    OnChannel(u8, Box<SynthEvent>), // A controller or patch change from one MIDI channel
+}
+
+struct VoiceSlot {
+    active: bool,
+    held: bool,
+    sostenuto: bool, // Caught by the sostenuto pedal, so it sounds until that lifts
+    sustained: bool, // Let go while the sustain pedal was down
+    channel: u8,
+    key: u8,
+    age: u64, // Notes played before this one, for stealing the oldest
+    oscillator: WavetableOscillator,
+}
+
+// A fixed pool of voices played from one preset. Slots are allocated up
+// front and reused, so starting and stopping notes never allocates.
+pub struct Synth {
+    sample_rate: u32,
+    wavetable: Arc<Wavetable>,
+    sample: Option<Arc<Sample>>,
+    preset: Box<Preset>,
+    template: WavetableOscillator,
+    voices: Vec<VoiceSlot>,
+    dying: Vec<WavetableOscillator>, // Stolen voices fading out
+    pluck_lines: Vec<Vec<f32>>, // Spare delay lines for string voices
+    spent: Vec<SynthEvent>, // Heap memory done with, to be freed off the audio thread
+    held_keys: Vec<(u8, u8, f32, u8)>, // Channel, key, frequency and velocity held in mono, oldest first
+    arp: Arpeggiator,
+    arp_next: Option<u64>, // Sample the next step starts on, while the arpeggiator runs
+    arp_note: Option<(u8, u8, u64)>, // Channel and key of the note it's playing, and the sample it ends on
+    voice_time: Duration, // Spent rendering voices since last taken
+    voice_renders: u32,
+    notes_played: u64,
+    samples: u64,
+    mod_wheel: f32,
+    bend: [f32; 16], // Pitch wheel per MIDI channel, -1.0 to 1.0
+    pressure: [f32; 16], // Channel pressure per MIDI channel, 0.0 to 1.0
+    timbre: [f32; 16],   // CC 74 per MIDI channel, 0.0 to 1.0
+    mpe_bend_range: Option<f32>, // Semitones, with MPE on
+    last_frequency: Option<f32>,
+    latch: bool, // Note-offs are ignored and keys toggle their notes
+    sustain: bool,
+    sostenuto: bool,
+    soft_pedal: bool,
+}
+
+impl Synth {
+    pub fn new(preset: Preset, wavetable: Arc<Wavetable>, sample: Option<Arc<Sample>>, sample_rate: u32) -> Synth {
+        let mut template = preset.oscillator(sample_rate, Arc::clone(&wavetable));
+        template.set_sample(sample.clone());
+        let voices = (0..MAX_VOICES)
+            .map(|_| VoiceSlot {
+                active: false,
+                held: false,
+                sostenuto: false,
+                sustained: false,
+                channel: 0,
+                key: 0,
+                age: 0,
+                oscillator: template.clone(),
+            })
+            .collect();
+        Synth {
+            sample_rate,
+            wavetable,
+            sample,
+            preset: Box::new(preset),
+            template,
+            voices,
+            dying: Vec::with_capacity(DYING_VOICES),
+            pluck_lines: (0..MAX_VOICES + DYING_VOICES).map(|_| vec![0.0; pluck::line_length(sample_rate)]).collect(),
+            spent: Vec::with_capacity(SPENT_EVENTS),
+            held_keys: Vec::with_capacity(HELD_KEYS),
+            arp: Arpeggiator::new(),
+            arp_next: None,
+            arp_note: None,
+            voice_time: Duration::ZERO,
+            voice_renders: 0,
+            notes_played: 0,
+            samples: 0,
+            mod_wheel: 0.0,
+            bend: [0.0; 16],
+            pressure: [0.0; 16],
+            timbre: [0.0; 16],
+            mpe_bend_range: None,
+            last_frequency: None,
+            latch: false,
+            sustain: false,
+            sostenuto: false,
+            soft_pedal: false,
+        }
+    }
+
+    pub fn handle(&mut self, event: SynthEvent) {
+        match event {
+            SynthEvent::NoteOn { channel, key, frequency, velocity } => {
+                self.note_on(channel, key, frequency, velocity)
+            }
+            SynthEvent::NoteOff { channel, key } => self.note_off(channel, key),
+            SynthEvent::PitchBend { channel, bend } => self.pitch_bend(channel, bend),
+            SynthEvent::Retune { key, frequency } => self.retune(key, frequency),
+            SynthEvent::Pressure { channel, key, pressure } => self.set_pressure(channel, key, pressure),
+            SynthEvent::Timbre { channel, timbre } => self.set_timbre(channel, timbre),
+            SynthEvent::Mpe(bend_range) => self.set_mpe(bend_range),
+            SynthEvent::Control(parameter, value) => self.set_parameter(parameter, value),
+            SynthEvent::ModWheel(value) => self.set_mod_wheel(value),
+            SynthEvent::Preset(preset) => self.set_preset(preset),
+            SynthEvent::Latch(latch) => self.set_latch(latch),
+            SynthEvent::Sustain(down) => self.set_sustain(down),
+            SynthEvent::Sostenuto(down) => self.set_sostenuto(down),
+            SynthEvent::SoftPedal(down) => self.soft_pedal = down,
+            SynthEvent::AllNotesOff => self.release_all(),
+            SynthEvent::AllSoundOff => self.all_sound_off(),
+            SynthEvent::SelectLayer(_) => (),
+            SynthEvent::OnChannel(channel, mut event) => {
+                self.handle(std::mem::replace(&mut *event, SynthEvent::AllNotesOff));
+                self.retire(SynthEvent::OnChannel(channel, event));
+            }
+        }
+    }
+
+    // Keeps an event holding heap memory for take_spent, so it isn't freed
+    // on the audio thread. Past SPENT_EVENTS in one block it's dropped here.
+    fn retire(&mut self, event: SynthEvent) {
+        if self.spent.len() < self.spent.capacity() {
+            self.spent.push(event);
+        }
+    }
+
+    // Presets and event boxes this synth is done with since the last call.
+    pub fn take_spent(&mut self) -> std::vec::Drain<'_, SynthEvent> {
+        self.spent.drain(..)
+    }
+
+    // Plays a note, or with the preset's arpeggiator on, adds the key to
+    // those it steps through.
+    pub fn note_on(&mut self, channel: u8, key: u8, frequency: f32, velocity: u8) {
+        if self.preset.arp.is_none() {
+            self.play_note(channel, key, frequency, velocity);
+            return;
+        }
+        // Latched, striking a held key lets go of it.
+        if self.latch && self.arp.release(channel, key) {
+            return;
+        }
+        self.arp.press(ArpNote { channel, key, frequency, velocity });
+        if self.arp_next.is_none() {
+            self.arp_next = Some(self.samples);
+        }
+    }
+
+    // Takes a free slot, or once the preset's polyphony is sounding steals a
+    // voice as its steal mode picks, and fades the stolen one out. Unless
+    // the preset stacks or cuts them, a key that's still sounding replays
+    // its own voice instead.
+    fn play_note(&mut self, channel: u8, key: u8, frequency: f32, velocity: u8) {
+        if self.latch && self.release(channel, key) {
+            return;
+        }
+        let velocity = if self.soft_pedal {
+            ((velocity as f32 * SOFT_PEDAL).round() as u8).max(1)
+        } else {
+            velocity
+        };
+        if self.preset.voice_mode != VoiceMode::Poly && self.mono_note_on(channel, key, frequency, velocity) {
+            return;
+        }
+
+        if self.preset.retrigger == Retrigger::Cut {
+            let sounding = self
+                .voices
+                .iter_mut()
+                .filter(|voice| voice.active && voice.channel == channel && voice.key == key);
+            for voice in sounding {
+                voice.held = false;
+                voice.sostenuto = false;
+                voice.sustained = false;
+                voice.oscillator.fade_out(PANIC_FADE);
+            }
+        } else if self.preset.retrigger != Retrigger::Stack {
+            let sounding = self
+                .voices
+                .iter_mut()
+                .filter(|voice| voice.active && voice.channel == channel && voice.key == key)
+                .max_by_key(|voice| voice.age);
+            if let Some(voice) = sounding {
+                self.preset.retrigger(&mut voice.oscillator, frequency, velocity, voice.held);
+                voice.held = true;
+                voice.sustained = false;
+                voice.age = self.notes_played;
+                self.notes_played += 1;
+                self.last_frequency = Some(frequency);
+                return;
+            }
+        }
+
+        let sounding = self.voices.iter().filter(|voice| voice.active).count();
+        let free = self.voices.iter().position(|voice| !voice.active);
+        let polyphony = match self.preset.voice_mode {
+            VoiceMode::Poly => self.preset.polyphony.clamp(1, MAX_VOICES),
+            VoiceMode::Mono | VoiceMode::Legato => 1,
+        };
+        let slot = match free {
+            Some(slot) if sounding < polyphony => slot,
+            _ => self.steal(channel, key),
+        };
+        // Free up a delay line first: the slot's own, or, as its voice is
+        // about to join the dying, the oldest of those if they're full.
+        if !self.voices[slot].active {
+            recycle_line(&mut self.pluck_lines, &mut self.voices[slot].oscillator);
+        } else if self.dying.len() == DYING_VOICES {
+            recycle_line(&mut self.pluck_lines, &mut self.dying.remove(0));
+        }
+
+        let time = self.time();
+        let seed = self.notes_played as u32;
+        let mut oscillator = self.template.clone();
+        if self.preset.wave_type == WaveType::Pluck {
+            oscillator.swap_pluck_line(&mut self.pluck_lines.pop().unwrap_or_default());
+        }
+        self.preset.start(&mut oscillator, frequency, velocity, time, seed);
+        oscillator.set_mod_wheel(self.mod_wheel);
+        oscillator.set_bend(self.bend_semitones(channel));
+        oscillator.set_pressure(self.pressure.get(channel as usize).copied().unwrap_or(0.0));
+        oscillator.set_timbre(self.timbre.get(channel as usize).copied().unwrap_or(0.0));
+        if let Some(last_frequency) = self.last_frequency {
+            self.preset.glide(&mut oscillator, last_frequency, frequency);
+        }
+        self.last_frequency = Some(frequency);
+
+        let voice = VoiceSlot {
+            active: true,
+            held: true,
+            sostenuto: false,
+            sustained: false,
+            channel,
+            key,
+            age: self.notes_played,
+            oscillator,
+        };
+        let stolen = std::mem::replace(&mut self.voices[slot], voice);
+        if stolen.active {
+            self.fade_stolen(stolen.oscillator);
+        }
+        self.notes_played += 1;
+    }
+
+    // Remembers a key struck in mono and, if it has priority, moves the held
+    // voice onto it. Returns false if no voice is held, so the note starts
+    // one as in poly.
+    fn mono_note_on(&mut self, channel: u8, key: u8, frequency: f32, velocity: u8) -> bool {
+        self.held_keys.retain(|&(held_channel, held_key, ..)| (held_channel, held_key) != (channel, key));
+        if self.held_keys.len() == HELD_KEYS {
+            self.held_keys.remove(0);
+        }
+        self.held_keys.push((channel, key, frequency, velocity));
+        if self.priority_key().is_some_and(|(_, priority, ..)| priority != key) {
+            return true;
+        }
+        let retrigger = (self.preset.voice_mode == VoiceMode::Mono).then_some(velocity);
+        self.mono_move(channel, key, frequency, retrigger)
+    }
+
+    // Lets go of a key in mono. If it was the one sounding and others are
+    // still down, the voice moves back to whichever of those has priority.
+    fn mono_note_off(&mut self, channel: u8, key: u8) {
+        self.held_keys.retain(|&(held_channel, held_key, ..)| (held_channel, held_key) != (channel, key));
+        let sounding = self
+            .voices
+            .iter()
+            .any(|voice| voice.active && voice.held && voice.channel == channel && voice.key == key);
+        if let Some((channel, key, frequency, velocity)) = self.priority_key().filter(|_| sounding) {
+            let retrigger = (self.preset.voice_mode == VoiceMode::Mono).then_some(velocity);
+            self.mono_move(channel, key, frequency, retrigger);
+        } else {
+            self.release(channel, key);
+        }
+    }
+
+    fn priority_key(&self) -> Option<(u8, u8, f32, u8)> {
+        match self.preset.note_priority {
+            NotePriority::Last => self.held_keys.last().copied(),
+            NotePriority::Low => self.held_keys.iter().min_by_key(|(_, key, ..)| *key).copied(),
+            NotePriority::High => self.held_keys.iter().max_by_key(|(_, key, ..)| *key).copied(),
+        }
+    }
+
+    // Glides the held mono voice to a new key, restarting its envelope from
+    // where it is at `retrigger` velocity if given. Returns whether there was
+    // a voice to move.
+    fn mono_move(&mut self, channel: u8, key: u8, frequency: f32, retrigger: Option<u8>) -> bool {
+        let bend = self.bend_semitones(channel);
+        let voice = self.voices.iter_mut().filter(|voice| voice.active && voice.held).max_by_key(|voice| voice.age);
+        let Some(voice) = voice else {
+            return false;
+        };
+        self.preset.retune(&mut voice.oscillator, frequency);
+        if let Some(last_frequency) = self.last_frequency {
+            self.preset.glide(&mut voice.oscillator, last_frequency, frequency);
+        }
+        if let Some(velocity) = retrigger {
+            voice.oscillator.set_velocity(self.preset.velocity_curve.apply(velocity));
+            voice.oscillator.restart_envelope(true);
+        }
+        voice.oscillator.set_bend(bend);
+        voice.channel = channel;
+        voice.key = key;
+        self.last_frequency = Some(frequency);
+        true
+    }
+
+    // The sounding voice a new note on `key` takes over.
+    fn steal(&self, channel: u8, key: u8) -> usize {
+        let sounding = || self.voices.iter().enumerate().filter(|(_, voice)| voice.active);
+        let oldest = || sounding().min_by_key(|(_, voice)| (voice.held, voice.age));
+        let stolen = match self.preset.voice_steal {
+            VoiceSteal::Oldest => oldest(),
+            VoiceSteal::Quietest => {
+                sounding().min_by(|(_, a), (_, b)| a.oscillator.level().total_cmp(&b.oscillator.level()))
+            }
+            VoiceSteal::SameNote => sounding()
+                .filter(|(_, voice)| voice.channel == channel && voice.key == key)
+                .min_by_key(|(_, voice)| voice.age)
+                .or_else(oldest),
+        };
+        stolen.map_or(0, |(slot, _)| slot)
+    }
+
+    // Lets a stolen voice fade out over PANIC_FADE instead of cutting it off
+    // with a click. note_on has already made room by cutting the oldest if
+    // DYING_VOICES were fading.
+    fn fade_stolen(&mut self, mut oscillator: WavetableOscillator) {
+        oscillator.fade_out(PANIC_FADE);
+        self.dying.push(oscillator);
+    }
+
+    // Ignored while latched.
+    pub fn note_off(&mut self, channel: u8, key: u8) {
+        if self.latch || self.preset.arp.is_some() && self.arp.release(channel, key) {
+            return;
+        }
+        self.stop_note(channel, key);
+    }
+
+    fn stop_note(&mut self, channel: u8, key: u8) {
+        match self.preset.voice_mode {
+            VoiceMode::Poly => {
+                self.release(channel, key);
+            }
+            VoiceMode::Mono | VoiceMode::Legato => self.mono_note_off(channel, key),
+        }
+    }
+
+    // Releases the oldest held voice on `key`, so a key struck twice stacks
+    // two voices and lets them go one note-off at a time. A voice the
+    // sostenuto pedal caught, or let go with the sustain pedal down, keeps
+    // sounding. Returns whether there was one.
+    fn release(&mut self, channel: u8, key: u8) -> bool {
+        let sustain = self.sustain;
+        let voice = self
+            .voices
+            .iter_mut()
+            .filter(|voice| voice.active && voice.held && voice.channel == channel && voice.key == key)
+            .min_by_key(|voice| voice.age);
+        let Some(voice) = voice else {
+            return false;
+        };
+        voice.held = false;
+        voice.sustained = sustain;
+        if !voice.sostenuto && !voice.sustained {
+            voice.oscillator.release();
+        }
+        true
+    }
+
+    // Bends every voice on `channel`, sounding or still to come, by `bend`
+    // (-1.0 to 1.0) of the preset's bend range.
+    pub fn pitch_bend(&mut self, channel: u8, bend: f32) {
+        let Some(channel_bend) = self.bend.get_mut(channel as usize) else {
+            return;
+        };
+        *channel_bend = bend.clamp(-1.0, 1.0);
+        self.update_bends();
+    }
+
+    // With MPE on, the first channel is the master channel, whose wheel
+    // bends every note by the preset's range, and each of the others bends
+    // just its own notes by the MPE range.
+    fn bend_semitones(&self, channel: u8) -> f32 {
+        let bend = |channel: u8| self.bend.get(channel as usize).copied().unwrap_or(0.0);
+        match self.mpe_bend_range {
+            Some(range) if channel != 0 => bend(0) * self.preset.pitch_bend_range + bend(channel) * range,
+            _ => bend(channel) * self.preset.pitch_bend_range,
+        }
+    }
+
+    fn update_bends(&mut self) {
+        let bends: [f32; 16] = std::array::from_fn(|channel| self.bend_semitones(channel as u8));
+        for voice in self.voices.iter_mut().filter(|voice| voice.active) {
+            voice.oscillator.set_bend(bends.get(voice.channel as usize).copied().unwrap_or(0.0));
+        }
+    }
+
+    // Turns MPE on, with member channels bending `bend_range` semitones, or
+    // off with None.
+    pub fn set_mpe(&mut self, bend_range: Option<f32>) {
+        self.mpe_bend_range = bend_range;
+        self.update_bends();
+    }
+
+    // Sets CC 74 for every voice on `channel`, sounding or still to come.
+    pub fn set_timbre(&mut self, channel: u8, timbre: f32) {
+        let Some(channel_timbre) = self.timbre.get_mut(channel as usize) else {
+            return;
+        };
+        *channel_timbre = timbre.clamp(0.0, 1.0);
+        for voice in self.voices.iter_mut().filter(|voice| voice.active && voice.channel == channel) {
+            voice.oscillator.set_timbre(*channel_timbre);
+        }
+    }
+
+    // Moves the wheel for every voice, sounding or still to come.
+    pub fn set_mod_wheel(&mut self, value: f32) {
+        self.mod_wheel = value;
+        for voice in self.voices.iter_mut().filter(|voice| voice.active) {
+            voice.oscillator.set_mod_wheel(value);
+        }
+    }
+
+    // Presses every voice on `channel`, sounding or still to come, or with a
+    // `key`, just the voices sounding on it.
+    pub fn set_pressure(&mut self, channel: u8, key: Option<u8>, pressure: f32) {
+        let pressure = pressure.clamp(0.0, 1.0);
+        if key.is_none() {
+            let Some(channel_pressure) = self.pressure.get_mut(channel as usize) else {
+                return;
+            };
+            *channel_pressure = pressure;
+        }
+        let pressed = |voice: &&mut VoiceSlot| {
+            voice.active && voice.channel == channel && key.is_none_or(|key| key == voice.key)
+        };
+        for voice in self.voices.iter_mut().filter(pressed) {
+            voice.oscillator.set_pressure(pressure);
+        }
+    }
+
+    // Moves the voices sounding on `key` to its new `frequency`, for a tuning
+    // that changes under them. Later notes get it from the tuning itself.
+    pub fn retune(&mut self, key: u8, frequency: f32) {
+        for voice in self.voices.iter_mut().filter(|voice| voice.active && voice.key == key) {
+            self.preset.retune(&mut voice.oscillator, frequency);
+        }
+    }
+
+    // While the pedal is down, notes let go keep sounding. Lifting it
+    // releases them, apart from any the sostenuto pedal still holds.
+    pub fn set_sustain(&mut self, down: bool) {
+        if down == self.sustain {
+            return;
+        }
+        self.sustain = down;
+        if down {
+            return;
+        }
+        for voice in self.voices.iter_mut().filter(|voice| voice.active && voice.sustained) {
+            voice.sustained = false;
+            if !voice.held && !voice.sostenuto {
+                voice.oscillator.release();
+            }
+        }
+    }
+
+    // Pressing the pedal catches the notes held at that moment, which then
+    // sound until it lifts even if their keys are let go. Notes played while
+    // it's down aren't caught.
+    pub fn set_sostenuto(&mut self, down: bool) {
+        if down == self.sostenuto {
+            return;
+        }
+        self.sostenuto = down;
+        for voice in self.voices.iter_mut().filter(|voice| voice.active) {
+            if down {
+                voice.sostenuto = voice.held;
+            } else if voice.sostenuto {
+                voice.sostenuto = false;
+                if !voice.held && !voice.sustained {
+                    voice.oscillator.release();
+                }
+            }
+        }
+    }
+
+    // While latched, notes sound until their key is struck again. Letting
+    // go of the latch releases them all.
+    pub fn set_latch(&mut self, latch: bool) {
+        if self.latch && !latch {
+            self.release_all();
+        }
+        self.latch = latch;
+    }
+
+    pub fn release_all(&mut self) {
+        self.held_keys.clear();
+        self.arp.clear();
+        let sounding = |voice: &&mut VoiceSlot| voice.active && (voice.held || voice.sostenuto || voice.sustained);
+        for voice in self.voices.iter_mut().filter(sounding) {
+            voice.held = false;
+            voice.sostenuto = false;
+            voice.sustained = false;
+            voice.oscillator.release();
+        }
+    }
+
+    // The panic button: fades out every voice, releasing or not, and drops
+    // the latch and pedals, so nothing is left sounding.
+    pub fn all_sound_off(&mut self) {
+        self.latch = false;
+        self.sustain = false;
+        self.sostenuto = false;
+        self.held_keys.clear();
+        self.arp.clear();
+        for voice in self.voices.iter_mut().filter(|voice| voice.active) {
+            voice.held = false;
+            voice.sostenuto = false;
+            voice.sustained = false;
+            voice.oscillator.fade_out(PANIC_FADE);
+        }
+    }
+
+    // Sounding voices keep their settings, apart from the bend range and
+    // the tempo their synced LFOs follow; new notes pick this up.
+    pub fn set_parameter(&mut self, parameter: Parameter, value: f32) {
+        parameter.set(&mut self.preset, value);
+        self.preset.apply(parameter, &mut self.template);
+        match parameter {
+            Parameter::PitchBendRange => self.update_bends(),
+            Parameter::Tempo => {
+                for voice in self.voices.iter_mut().filter(|voice| voice.active) {
+                    self.preset.sync_lfos(&mut voice.oscillator);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // Switches patch, letting sounding voices ring out as they were, though
+    // bent by the new patch's range.
+    // The old preset goes to take_spent.
+    pub fn set_preset(&mut self, preset: Box<Preset>) {
+        let old = std::mem::replace(&mut self.preset, preset);
+        self.retire(SynthEvent::Preset(old));
+        self.update_template();
+        self.update_bends();
+    }
+
+    // Carries on at `sample_rate`, with sounding voices keeping their pitch
+    // and how far through their envelopes they've got.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        let rescale = |samples: u64| (samples as f64 * sample_rate as f64 / self.sample_rate as f64) as u64;
+        self.samples = rescale(self.samples);
+        self.arp_next = self.arp_next.map(rescale);
+        self.arp_note = self.arp_note.map(|(channel, key, end)| (channel, key, rescale(end)));
+        self.sample_rate = sample_rate;
+        for voice in self.voices.iter_mut() {
+            voice.oscillator.set_sample_rate(sample_rate);
+        }
+        for oscillator in self.dying.iter_mut() {
+            oscillator.set_sample_rate(sample_rate);
+        }
+        for line in self.pluck_lines.iter_mut() {
+            line.resize(pluck::line_length(sample_rate), 0.0);
+        }
+        self.update_template();
+    }
+
+    fn update_template(&mut self) {
+        self.template = self.preset.oscillator(self.sample_rate, Arc::clone(&self.wavetable));
+        self.template.set_sample(self.sample.clone());
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    // The channel and key of every voice sounding, not counting stolen ones
+    // still fading out.
+    pub fn sounding(&self) -> impl Iterator<Item = (u8, u8)> + '_ {
+        self.voices.iter().filter(|voice| voice.active).map(|voice| (voice.channel, voice.key))
+    }
+
+    pub fn is_silent(&self) -> bool {
+        !self.voices.iter().any(|voice| voice.active) && self.dying.is_empty()
+    }
+
+    fn time(&self) -> f64 {
+        self.samples as f64 / self.sample_rate as f64
+    }
+
+    // Adds every sounding voice into interleaved stereo `buffer`, stopping
+    // at each arpeggiator step to play its note.
+    pub fn render(&mut self, buffer: &mut [f32]) {
+        let mut start = 0;
+        while start < buffer.len() {
+            self.step_arp();
+            let frames = (buffer.len() - start) / 2;
+            let due = self.arp_next.into_iter().chain(self.arp_note.map(|(.., end)| end)).min();
+            let frames = due.map_or(frames, |due| (due.saturating_sub(self.samples) as usize).clamp(1, frames));
+            self.render_voices(&mut buffer[start..start + frames * 2]);
+            start += frames * 2;
+        }
+    }
+
+    // Ends the arpeggiator's note once its gate is up, and plays the next
+    // one when a step is due. With no keys left, or the arpeggiator turned
+    // off, it stops until a key is struck.
+    fn step_arp(&mut self) {
+        if let Some((channel, key, end)) = self.arp_note {
+            if end <= self.samples || self.preset.arp.is_none() {
+                self.arp_note = None;
+                self.stop_note(channel, key);
+            }
+        }
+        let Some(settings) = self.preset.arp else {
+            self.arp.clear();
+            self.arp_next = None;
+            return;
+        };
+        if self.arp_next.is_none_or(|next| next > self.samples) {
+            return;
+        }
+        let Some(note) = self.arp.next_note(settings.pattern, settings.octaves) else {
+            self.arp_next = None;
+            return;
+        };
+        let step = settings.rate.beats() * 60.0 / self.preset.tempo * self.sample_rate as f32;
+        let step = (step as u64).max(1);
+        let gate = ((step as f32 * settings.gate.clamp(0.05, 1.0)) as u64).clamp(1, step);
+        self.play_note(note.channel, note.key, note.frequency, note.velocity);
+        self.arp_note = Some((note.channel, note.key, self.samples + gate));
+        self.arp_next = Some(self.samples + step);
+    }
+
+    // Adds every sounding voice into interleaved stereo `buffer`, timing
+    // each one.
+    fn render_voices(&mut self, buffer: &mut [f32]) {
+        let started = Instant::now();
+        let mut renders = 0;
+        for voice in self.voices.iter_mut().filter(|voice| voice.active) {
+            voice.oscillator.render(buffer);
+            if voice.oscillator.is_finished() {
+                voice.active = false;
+            }
+            renders += 1;
+        }
+        for oscillator in self.dying.iter_mut() {
+            oscillator.render(buffer);
+            renders += 1;
+        }
+        while let Some(finished) = self.dying.iter().position(|oscillator| oscillator.is_finished()) {
+            recycle_line(&mut self.pluck_lines, &mut self.dying.remove(finished));
+        }
+        self.samples += (buffer.len() / 2) as u64;
+        if renders > 0 {
+            self.voice_time += started.elapsed();
+            self.voice_renders += renders;
+        }
+    }
+
+    // Time spent rendering voices since the last call, and how many voice
+    // renders it was spread across.
+    pub fn take_voice_time(&mut self) -> (Duration, u32) {
+        let taken = (self.voice_time, self.voice_renders);
+        self.voice_time = Duration::ZERO;
+        self.voice_renders = 0;
+        taken
+    }
+}
+
+// Takes back a string voice's delay line for the next one. There are enough
+// lines for every slot and dying voice, so this never grows the pool.
+fn recycle_line(lines: &mut Vec<Vec<f32>>, oscillator: &mut WavetableOscillator) {
+    let mut line = Vec::new();
+    oscillator.swap_pluck_line(&mut line);
+    if !line.is_empty() {
+        lines.push(line);
+    }
+}
+
+// A retune for every key `after` plays at a different frequency from
+// `before`, so notes already sounding follow a tuning change.
+pub fn retunes(before: &Tuning, after: &Tuning) -> Vec<SynthEvent> {
+    (0..=127)
+        .filter_map(|key| match after.frequency(key) {
+            Some(frequency) if before.frequency(key) != Some(frequency) => Some(SynthEvent::Retune { key, frequency }),
+            _ => None,
+        })
+        .collect()
+}
+
+// How long rendering takes as a fraction of the time the audio it renders
+// lasts, averaged over recent blocks. Past 1.0 the output falls behind.
+// Handles are cheap to clone and read from any thread.
+#[derive(Clone, Debug, Default)]
+pub struct CpuLoad {
+    total: Arc<AtomicU32>,
+    voice: Arc<AtomicU32>,
+}
+
+impl CpuLoad {
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.total.load(Ordering::Relaxed))
+    }
+
+    // The share of the budget one voice takes, averaged over the voices
+    // sounding in each block, for judging how many more would fit.
+    pub fn per_voice(&self) -> f32 {
+        f32::from_bits(self.voice.load(Ordering::Relaxed))
+    }
+
+    fn update(&self, load: f32, voice_load: Option<f32>) {
+        smooth(&self.total, load);
+        if let Some(voice_load) = voice_load {
+            smooth(&self.voice, voice_load);
+        }
+    }
+}
+
+fn smooth(average: &AtomicU32, load: f32) {
+    let current = f32::from_bits(average.load(Ordering::Relaxed));
+    average.store((current + (load - current) * LOAD_SMOOTHING).to_bits(), Ordering::Relaxed);
+}
+
+// Feeds a synth to the audio output, applying events from `events` between blocks.
+// Ends once the sender is gone and the last voice has finished.
+pub struct SynthSource {
+    synth: LayeredSynth,
+    master: MasterBus,
+    events: Receiver<SynthEvent>,
+    spent: SyncSender<SynthEvent>,
+    disconnected: bool,
+    block: [f32; BLOCK_SIZE * 2],
+    block_position: usize,
+    cpu_load: CpuLoad,
+}
+
+impl SynthSource {
+    // Spent presets and event boxes are dropped on a thread of their own,
+    // so freeing them never holds up a block.
+    pub fn new(synth: LayeredSynth, events: Receiver<SynthEvent>) -> SynthSource {
+        let (spent, dropped) = mpsc::sync_channel(SPENT_EVENTS);
+        thread::spawn(move || dropped.iter().for_each(drop));
+        SynthSource {
+            master: MasterBus::new(synth.master(), synth.sample_rate()),
+            synth,
+            events,
+            spent,
+            disconnected: false,
+            block: [0.0; BLOCK_SIZE * 2],
+            block_position: BLOCK_SIZE * 2,
+            cpu_load: CpuLoad::default(),
+        }
+    }
+
+    // A handle for watching the render load once the source is playing.
+    pub fn cpu_load(&self) -> CpuLoad {
+        self.cpu_load.clone()
+    }
+
+    // A handle for watching the master bus once the source is playing.
+    pub fn master_meter(&self) -> MasterMeter {
+        self.master.meter()
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.synth.set_sample_rate(sample_rate);
+        self.master.set_sample_rate(sample_rate);
+    }
+}
+
+impl Iterator for SynthSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.block_position == self.block.len() {
+            while !self.disconnected {
+                match self.events.try_recv() {
+                    Ok(event) => self.synth.handle(event),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => self.disconnected = true,
+                }
+            }
+            for event in self.synth.take_spent() {
+                // With the dropping thread behind, this one frees it instead.
+                let _ = self.spent.try_send(event);
+            }
+            if self.disconnected && self.synth.is_silent() {
+                return None;
+            }
+            let started = Instant::now();
+            self.block = [0.0; BLOCK_SIZE * 2];
+            self.synth.render(&mut self.block);
+            self.master.process(&mut self.block);
+            self.block_position = 0;
+            let budget = BLOCK_SIZE as f32 / self.synth.sample_rate() as f32;
+            let (voice_time, voice_renders) = self.synth.take_voice_time();
+            let voice_load = (voice_renders > 0).then(|| voice_time.as_secs_f32() / voice_renders as f32 / budget);
+            self.cpu_load.update(started.elapsed().as_secs_f32() / budget, voice_load);
+        }
+
+        let sample = self.block[self.block_position];
+        self.block_position += 1;
+        Some(sample)
+    }
+}
@@ -0,0 +1,86 @@
+use crate::error::SynthError;
+use crate::preset::Preset;
+
+const MANUFACTURER_ID: u8 = 0x7D; // Set aside for non-commercial use
+const DEVICE_ID: u8 = 0x00;
+const ALL_DEVICES: u8 = 0x7F;
+const DUMP_REQUEST: u8 = 0x01;
+const PRESET_DUMP: u8 = 0x02;
+
+pub enum SysexMessage {
+    DumpRequest,
+    PresetDump(Box<Preset>),
+}
+
+// F0 7D <device> 01 F7 asks the synth to send its current preset.
+pub fn dump_request() -> Vec<u8> {
+    vec![0xF0, MANUFACTURER_ID, DEVICE_ID, DUMP_REQUEST, 0xF7]
+}
+
+// F0 7D <device> 02 <data> <checksum> F7, where the data is the preset's
+// JSON packed into seven-bit bytes, so it restores everything a preset file
+// would.
+pub fn encode_preset(preset: &Preset) -> Result<Vec<u8>, SynthError> {
+    let data = pack(&serde_json::to_vec(preset)?);
+    let mut message = vec![0xF0, MANUFACTURER_ID, DEVICE_ID, PRESET_DUMP];
+    message.extend_from_slice(&data);
+    message.push(checksum(&data));
+    message.push(0xF7);
+    Ok(message)
+}
+
+// None for sysex addressed to some other device or manufacturer.
+pub fn decode(message: &[u8]) -> Result<Option<SysexMessage>, SynthError> {
+    let [0xF0, MANUFACTURER_ID, device, command, body @ .., 0xF7] = message else {
+        return Ok(None);
+    };
+    if *device != DEVICE_ID && *device != ALL_DEVICES {
+        return Ok(None);
+    }
+
+    match (*command, body) {
+        (DUMP_REQUEST, []) => Ok(Some(SysexMessage::DumpRequest)),
+        (PRESET_DUMP, [data @ .., sum]) => {
+            if data.iter().any(|byte| byte & 0x80 != 0) {
+                return Err(invalid("data byte above 0x7F"));
+            }
+            if checksum(data) != *sum {
+                return Err(invalid("checksum mismatch"));
+            }
+            let preset = serde_json::from_slice(&unpack(data))?;
+            Ok(Some(SysexMessage::PresetDump(Box::new(preset))))
+        }
+        _ => Err(invalid(&format!("unknown command {:#04x}", command))),
+    }
+}
+
+// Each run of up to seven bytes is sent as a byte holding their high bits,
+// lowest first, followed by the seven low parts.
+fn pack(bytes: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(bytes.len() + bytes.len().div_ceil(7));
+    for chunk in bytes.chunks(7) {
+        let high_bits = chunk.iter().enumerate().fold(0, |bits, (i, byte)| bits | (byte >> 7) << i);
+        data.push(high_bits);
+        data.extend(chunk.iter().map(|byte| byte & 0x7F));
+    }
+    data
+}
+
+fn unpack(data: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(data.len());
+    for chunk in data.chunks(8) {
+        let (high_bits, low_parts) = chunk.split_first().unwrap_or((&0, &[]));
+        bytes.extend(low_parts.iter().enumerate().map(|(i, byte)| byte | ((high_bits >> i) & 1) << 7));
+    }
+    bytes
+}
+
+// Roland-style: the data and checksum sum to a multiple of 128.
+fn checksum(data: &[u8]) -> u8 {
+    let sum = data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    sum.wrapping_neg() & 0x7F
+}
+
+fn invalid(message: &str) -> SynthError {
+    SynthError::Sysex(String::from(message))
+}
@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::error::SynthError;
+
+// Maps MIDI keys to frequencies through a scale and a keyboard mapping, using
+// the same model as Scala's .scl and .kbm files.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tuning {
+    scale: Vec<f64>, // Cents of degrees 1..=n above the root; the last is the period
+    mapping: KeyboardMapping,
+    retuned: HashMap<u8, f64>, // Frequencies set by MIDI Tuning Standard messages
+    master_tune: f64, // Cents, applied to every key
+    transpose: i32,   // Keys to shift every note by
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyboardMapping {
+    first_key: u8,
+    last_key: u8,
+    middle_key: u8, // Plays the scale's root
+    reference_key: u8,
+    reference_frequency: f64,
+    octave_degree: usize, // Degree that repeats the pattern, zero for the period
+    keys: Vec<Option<usize>>, // Degree per key from the middle key; empty maps every key in turn
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Tuning::equal(12)
+    }
+}
+
+impl Default for KeyboardMapping {
+    fn default() -> Self {
+        Self {
+            first_key: 0,
+            last_key: 127,
+            middle_key: 60,
+            reference_key: 69,
+            reference_frequency: 440.0,
+            octave_degree: 0,
+            keys: Vec::new(),
+        }
+    }
+}
+
+impl Tuning {
+    pub fn equal(divisions: usize) -> Tuning {
+        let divisions = divisions.max(1);
+        Tuning {
+            scale: (1..=divisions).map(|step| 1200.0 * step as f64 / divisions as f64).collect(),
+            mapping: KeyboardMapping::default(),
+            retuned: HashMap::new(),
+            master_tune: 0.0,
+            transpose: 0,
+        }
+    }
+
+    // Five-limit just intonation rooted on C.
+    pub fn just_intonation() -> Tuning {
+        let ratios = [
+            (16, 15), (9, 8), (6, 5), (5, 4), (4, 3), (45, 32),
+            (3, 2), (8, 5), (5, 3), (9, 5), (15, 8), (2, 1),
+        ];
+        Tuning {
+            scale: ratios.iter().map(|&(n, d)| ratio_cents(n as f64 / d as f64)).collect(),
+            mapping: KeyboardMapping::default(),
+            retuned: HashMap::new(),
+            master_tune: 0.0,
+            transpose: 0,
+        }
+    }
+
+    // 24 steps per octave, a quarter tone per key.
+    pub fn quarter_tone() -> Tuning {
+        Tuning::equal(24)
+    }
+
+    pub fn load_scale(&mut self, path: &Path) -> Result<(), SynthError> {
+        self.scale = parse_scl(&fs::read_to_string(path)?)?;
+        Ok(())
+    }
+
+    pub fn load_mapping(&mut self, path: &Path) -> Result<(), SynthError> {
+        self.mapping = KeyboardMapping::parse(&fs::read_to_string(path)?)?;
+        Ok(())
+    }
+
+    // The frequency the reference key plays, 440.0 for A4 by default. Must be
+    // above zero.
+    pub fn set_reference_frequency(&mut self, frequency: f32) -> Result<(), SynthError> {
+        self.mapping.reference_frequency = check_reference(frequency as f64)?;
+        Ok(())
+    }
+
+    // Detunes everything by up to a semitone either way, for playing along
+    // with recordings that aren't at concert pitch.
+    pub fn set_master_tune(&mut self, cents: f32) {
+        self.master_tune = cents.clamp(-100.0, 100.0) as f64;
+    }
+
+    // Shifts every note by up to two octaves of keys, so semitones in
+    // twelve-tone tunings.
+    pub fn set_transpose(&mut self, keys: i32) {
+        self.transpose = keys.clamp(-24, 24);
+    }
+
+    // None for keys the mapping leaves unplayed, or transposed off the
+    // keyboard.
+    pub fn frequency(&self, key: u8) -> Option<f32> {
+        let key = u8::try_from(key as i32 + self.transpose).ok().filter(|key| *key <= 127)?;
+        let frequency = match self.retuned.get(&key) {
+            Some(frequency) => *frequency,
+            None => {
+                let cents = self.key_cents(key)?;
+                let reference_cents = self.key_cents(self.mapping.reference_key).unwrap_or(0.0);
+                self.mapping.reference_frequency * 2.0_f64.powf((cents - reference_cents) / 1200.0)
+            }
+        };
+        Some((frequency * 2.0_f64.powf(self.master_tune / 1200.0)) as f32)
+    }
+
+    // Retunes keys from a MIDI Tuning Standard sysex message: a single note
+    // tuning change, a bulk tuning dump, or a scale/octave tuning. Returns
+    // false for any other message. Every tuning program and device ID is
+    // accepted, since there is only the one tuning to change.
+    pub fn apply_mts(&mut self, message: &[u8]) -> bool {
+        let body = match message {
+            [0xF0, rest @ .., 0xF7] => rest,
+            _ => return false,
+        };
+        match body {
+            // Single note tuning change, real-time and with a bank.
+            [0x7F, _, 0x08, 0x02, _program, count, changes @ ..]
+            | [0x7E, _, 0x08, 0x07, _, _program, count, changes @ ..] => {
+                for change in changes.chunks_exact(4).take(*count as usize) {
+                    self.retune(change[0], &change[1..]);
+                }
+            }
+            // Bulk dump: a 16 byte name then all 128 keys, and a checksum.
+            [0x7E, _, 0x08, 0x01, _program, rest @ ..] if rest.len() >= 16 + 128 * 3 => {
+                for (key, data) in rest[16..].chunks_exact(3).take(128).enumerate() {
+                    self.retune(key as u8, data);
+                }
+            }
+            // Scale/octave tuning: cents offsets from equal temperament for
+            // each pitch class, in one byte or two.
+            [0x7E | 0x7F, _, 0x08, form @ (0x08 | 0x09), _, _, _, offsets @ ..] => {
+                let size = if *form == 0x08 { 1 } else { 2 };
+                if offsets.len() < 12 * size {
+                    return false;
+                }
+                for key in 0..=127u8 {
+                    let offset = &offsets[(key % 12) as usize * size..][..size];
+                    let cents = match offset {
+                        [value] => *value as f64 - 64.0,
+                        [msb, lsb] => (((*msb as i32) << 7 | *lsb as i32) - 8192) as f64 / 8192.0 * 100.0,
+                        _ => 0.0,
+                    };
+                    self.retuned.insert(key, equal_frequency(key as f64 + cents / 100.0));
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    // Applies one three-byte MTS frequency: a semitone on the 12-TET note
+    // table and a 14-bit fraction of the next one. 7F 7F 7F means no change.
+    fn retune(&mut self, key: u8, data: &[u8]) {
+        let &[semitone, msb, lsb] = data else { return };
+        if key > 127 || [semitone, msb, lsb] == [0x7F; 3] {
+            return;
+        }
+        let fraction = ((msb as u32) << 7 | lsb as u32) as f64 / 16384.0;
+        self.retuned.insert(key, equal_frequency(semitone as f64 + fraction));
+    }
+
+    // Cents above the middle key.
+    fn key_cents(&self, key: u8) -> Option<f64> {
+        let mapping = &self.mapping;
+        if key < mapping.first_key || key > mapping.last_key {
+            return None;
+        }
+
+        let offset = key as i64 - mapping.middle_key as i64;
+        if mapping.keys.is_empty() {
+            return Some(self.degree_cents(offset));
+        }
+
+        let size = mapping.keys.len() as i64;
+        let degree = mapping.keys[offset.rem_euclid(size) as usize]?;
+        let octave_degree = match mapping.octave_degree {
+            0 => self.scale.len(),
+            degree => degree,
+        };
+        let octave_cents = self.degree_cents(octave_degree as i64);
+        Some(offset.div_euclid(size) as f64 * octave_cents + self.degree_cents(degree as i64))
+    }
+
+    fn degree_cents(&self, degree: i64) -> f64 {
+        let steps = self.scale.len() as i64;
+        let period = self.scale[self.scale.len() - 1];
+        let step = degree.rem_euclid(steps) as usize;
+        let cents = if step == 0 { 0.0 } else { self.scale[step - 1] };
+        degree.div_euclid(steps) as f64 * period + cents
+    }
+}
+
+impl KeyboardMapping {
+    pub fn parse(text: &str) -> Result<KeyboardMapping, SynthError> {
+        let mut fields = data_lines(text).map(|line| line.split_whitespace().next().unwrap_or(""));
+        let mut field = |name: &str| fields.next().ok_or_else(|| invalid(&format!("missing {}", name)));
+
+        let size: usize = parse_field(field("map size")?, "map size")?;
+        let mut mapping = KeyboardMapping {
+            first_key: parse_field(field("first note")?, "first note")?,
+            last_key: parse_field(field("last note")?, "last note")?,
+            middle_key: parse_field(field("middle note")?, "middle note")?,
+            reference_key: parse_field(field("reference note")?, "reference note")?,
+            reference_frequency: check_reference(parse_field(field("reference frequency")?, "reference frequency")?)?,
+            octave_degree: parse_field(field("octave degree")?, "octave degree")?,
+            keys: Vec::with_capacity(size),
+        };
+        // Files may stop early; the remaining keys are unmapped.
+        for _ in 0..size {
+            let degree = match field("key").unwrap_or("x") {
+                "x" => None,
+                degree => Some(parse_field(degree, "key degree")?),
+            };
+            mapping.keys.push(degree);
+        }
+        Ok(mapping)
+    }
+}
+
+// Reads the pitches of a Scala .scl file as cents above the root.
+pub fn parse_scl(text: &str) -> Result<Vec<f64>, SynthError> {
+    let mut lines = data_lines(text).skip(1); // The description
+    let count: usize = match lines.next() {
+        Some(line) => parse_field(line.split_whitespace().next().unwrap_or(""), "note count")?,
+        None => return Err(invalid("missing note count")),
+    };
+    if count == 0 {
+        return Err(invalid("scale has no notes"));
+    }
+
+    let mut scale = Vec::with_capacity(count);
+    for _ in 0..count {
+        let pitch = lines.next().and_then(|line| line.split_whitespace().next());
+        let pitch = pitch.ok_or_else(|| invalid("fewer pitches than the note count"))?;
+        let cents = if pitch.contains('.') {
+            parse_field(pitch, "pitch")?
+        } else {
+            let (numerator, denominator) = pitch.split_once('/').unwrap_or((pitch, "1"));
+            let ratio = parse_field::<f64>(numerator, "ratio")? / parse_field::<f64>(denominator, "ratio")?;
+            if ratio <= 0.0 || !ratio.is_finite() {
+                return Err(invalid(&format!("bad ratio {}", pitch)));
+            }
+            ratio_cents(ratio)
+        };
+        scale.push(cents);
+    }
+    Ok(scale)
+}
+
+fn check_reference(frequency: f64) -> Result<f64, SynthError> {
+    if frequency.is_finite() && frequency > 0.0 {
+        Ok(frequency)
+    } else {
+        Err(invalid(&format!("bad reference frequency {}", frequency)))
+    }
+}
+
+// The 12-TET frequency of a fractional MIDI note, as MTS defines it.
+fn equal_frequency(note: f64) -> f64 {
+    440.0 * 2.0_f64.powf((note - 69.0) / 12.0)
+}
+
+fn ratio_cents(ratio: f64) -> f64 {
+    1200.0 * ratio.log2()
+}
+
+// Scala files mark comments with a leading '!'.
+fn data_lines(text: &str) -> impl Iterator<Item = &str> {
+    text.lines().filter(|line| !line.starts_with('!')).map(str::trim)
+}
+
+fn parse_field<T: std::str::FromStr>(field: &str, name: &str) -> Result<T, SynthError> {
+    field.parse().map_err(|_| invalid(&format!("bad {} {:?}", name, field)))
+}
+
+fn invalid(message: &str) -> SynthError {
+    SynthError::TuningFormat(String::from(message))
+}
@@ -0,0 +1,216 @@
+use std::f32::consts::PI;
+use std::fs;
+use std::path::Path;
+
+use crate::error::SynthError;
+use crate::render;
+
+pub const TABLE_SIZE: usize = 2048;
+const LEVELS: usize = 11; // log2(TABLE_SIZE / 2) + 1, down to a lone fundamental
+
+// A set of single-cycle frames that can be morphed between, stored once per
+// octave with the harmonics above Nyquist for that octave removed.
+pub struct Wavetable {
+    levels: Vec<Vec<Vec<f32>>>,
+}
+
+impl Wavetable {
+    pub fn from_frames(frames: &[Vec<f32>]) -> Wavetable {
+        let spectra = frames
+            .iter()
+            .map(|frame| {
+                let mut re: Vec<f32> = (0..TABLE_SIZE)
+                    .map(|i| frame[i * frame.len() / TABLE_SIZE])
+                    .collect();
+                let mut im = vec![0.0; TABLE_SIZE];
+                fft(&mut re, &mut im, false);
+                (re, im)
+            })
+            .collect();
+        Wavetable::from_spectra(spectra)
+    }
+
+    // Imports a folder of single-cycle WAVs, as in the AKWF packs, one frame
+    // per file in name order. Each cycle is mixed to mono and resampled to
+    // the table size; the frames come out peak-normalised like any other.
+    pub fn load_dir(dir: &Path) -> Result<Wavetable, SynthError> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("wav")) {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        let mut frames = Vec::with_capacity(paths.len());
+        for path in &paths {
+            let (cycle, _) = render::read_wav(path)?;
+            if !cycle.is_empty() {
+                frames.push(resample(&cycle, TABLE_SIZE));
+            }
+        }
+        if frames.is_empty() {
+            return Err(SynthError::NoWavetableFrames(dir.to_path_buf()));
+        }
+        Ok(Wavetable::from_frames(&frames))
+    }
+
+    // Builds frames additively from `amplitude(frame, harmonic)`, where each
+    // harmonic is a sine starting at zero phase.
+    pub fn from_harmonics(frame_count: usize, amplitude: impl Fn(usize, usize) -> f32) -> Wavetable {
+        let spectra = (0..frame_count)
+            .map(|frame| {
+                let re = vec![0.0; TABLE_SIZE];
+                let mut im = vec![0.0; TABLE_SIZE];
+                for harmonic in 1..TABLE_SIZE / 2 {
+                    let bin = amplitude(frame, harmonic) * TABLE_SIZE as f32 / 2.0;
+                    im[harmonic] = -bin;
+                    im[TABLE_SIZE - harmonic] = bin;
+                }
+                (re, im)
+            })
+            .collect();
+        Wavetable::from_spectra(spectra)
+    }
+
+    // Sine, triangle, square and sawtooth, in that order.
+    pub fn basic_shapes() -> Wavetable {
+        Wavetable::from_harmonics(4, |frame, harmonic| {
+            let n = harmonic as f32;
+            match frame {
+                0 if harmonic == 1 => 1.0,
+                1 if harmonic % 2 == 1 => {
+                    let sign = if harmonic % 4 == 1 { 1.0 } else { -1.0 };
+                    sign / (n * n)
+                }
+                2 if harmonic % 2 == 1 => 1.0 / n,
+                3 => 1.0 / n,
+                _ => 0.0,
+            }
+        })
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    // `position` morphs across the frames from 0.0 to 1.0. `index_increment`
+    // is the playback step through the table, used to pick a mip level.
+    pub fn sample(&self, position: f32, index: f32, index_increment: f32) -> f32 {
+        let frames = &self.levels[Wavetable::level_for(index_increment)];
+
+        let frame_position = position.clamp(0.0, 1.0) * (frames.len() - 1) as f32;
+        let frame = frame_position as usize;
+        let next_frame = (frame + 1).min(frames.len() - 1);
+        let next_frame_weight = frame_position - frame as f32;
+
+        (1.0 - next_frame_weight) * lerp(&frames[frame], index)
+            + next_frame_weight * lerp(&frames[next_frame], index)
+    }
+
+    fn level_for(index_increment: f32) -> usize {
+        // Level n keeps TABLE_SIZE / 2 >> n harmonics, which stays below
+        // Nyquist while the table is stepped through at most 2^n per sample.
+        if index_increment <= 1.0 {
+            0
+        } else {
+            (index_increment.log2().ceil() as usize).min(LEVELS - 1)
+        }
+    }
+
+    fn from_spectra(spectra: Vec<(Vec<f32>, Vec<f32>)>) -> Wavetable {
+        let mut levels = vec![Vec::new(); LEVELS];
+
+        for (re, im) in spectra {
+            let mut gain = 1.0;
+            for (level, frames) in levels.iter_mut().enumerate() {
+                let max_harmonic = (TABLE_SIZE / 2) >> level;
+                let mut level_re = re.clone();
+                let mut level_im = im.clone();
+                for (bin, (bin_re, bin_im)) in level_re.iter_mut().zip(level_im.iter_mut()).enumerate() {
+                    let harmonic = bin.min(TABLE_SIZE - bin);
+                    if harmonic == 0 || harmonic > max_harmonic {
+                        *bin_re = 0.0;
+                        *bin_im = 0.0;
+                    }
+                }
+                fft(&mut level_re, &mut level_im, true);
+
+                // Normalise every level by the full-bandwidth peak so the
+                // volume doesn't jump between octaves.
+                if level == 0 {
+                    let peak = level_re.iter().fold(0.0_f32, |peak, s| peak.max(s.abs()));
+                    if peak > 0.0 {
+                        gain = 1.0 / peak;
+                    }
+                }
+                frames.push(level_re.iter().map(|s| s * gain).collect());
+            }
+        }
+
+        Wavetable { levels }
+    }
+}
+
+// Stretches one cycle to `len` samples, interpolating around the loop.
+fn resample(cycle: &[f32], len: usize) -> Vec<f32> {
+    let step = cycle.len() as f32 / len as f32;
+    (0..len).map(|i| lerp(cycle, i as f32 * step)).collect()
+}
+
+fn lerp(table: &[f32], index: f32) -> f32 {
+    let truncated_index = index as usize % table.len();
+    let next_index = (truncated_index + 1) % table.len();
+
+    let next_index_weight = index - index.floor();
+    let truncated_index_weight = 1.0 - next_index_weight;
+
+    truncated_index_weight * table[truncated_index]
+        + next_index_weight * table[next_index]
+}
+
+// In-place radix-2 FFT; `re.len()` must be a power of two.
+pub fn fft(re: &mut [f32], im: &mut [f32], inverse: bool) {
+    let n = re.len();
+
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j ^= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let angle = sign * 2.0 * PI * k as f32 / len as f32;
+                let (w_im, w_re) = angle.sin_cos();
+                let a = start + k;
+                let b = a + len / 2;
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+
+    if inverse {
+        for value in re.iter_mut().chain(im.iter_mut()) {
+            *value /= n as f32;
+        }
+    }
+}
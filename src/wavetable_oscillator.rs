@@ -1,94 +1,632 @@
 use crate::envelope::ADSR;
-use std::time::Duration; // Add the missing envelope module to the crate root.
+use crate::lfo::{self, LfoTarget, LFO};
+use crate::mod_matrix::{self, ModSlot, ModSources, MOD_SLOTS};
+use crate::pluck::Pluck;
+use crate::sampler::Sample;
+use crate::wavetable::{Wavetable, TABLE_SIZE};
+use std::f32::consts::{FRAC_1_SQRT_2, FRAC_PI_4, PI};
+use std::sync::Arc;
 
-use rodio::Source;
+use serde::{Deserialize, Serialize};
 
+const MIDDLE_C: f32 = 261.6256;
+pub const MAX_UNISON: usize = 8;
+const SUB_DRIFT: usize = MAX_UNISON; // Drift slot for the sub-oscillator, after the unison voices
+const DRIFT_INTERVAL: f32 = 0.5; // Seconds between new drift targets
+const CENTS_TO_RATIO: f32 = 0.000_577_6; // ln(2) / 1200, close enough for a few cents
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum GlideCurve {
+    Linear,      // Even semitones per second
+    Exponential, // Fast at first, settling into the note
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum WaveType {
+    Wavetable,
+    Pluck, // A Karplus-Strong string in place of the wavetable
+    Fm,    // The wavetable phase-modulated by a sine at a ratio of the note
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SubShape {
+    Sine,
+    Square,
+}
+
+#[derive(Clone)]
 pub struct WavetableOscillator {
     sample_rate: u32,
-    wave_table: Vec<f32>,
-    index: f32,
+    wavetable: Arc<Wavetable>,
+    wave_type: WaveType,
+    wave_level: f32,
+    pluck: Pluck,
+    fm_ratio: f32,
+    fm_index: f32,
+    fm_env_amount: f32,
+    fm_env_decay: f32,
+    fm_phase: f32,
+    position: f32,
+    indices: [f32; MAX_UNISON],
     index_increment: f32,
     volume: f32,
+    velocity: f32,
+    pan: f32,
+    unison_voices: usize,
+    unison_detune: f32,
+    unison_spread: f32,
+    random_phase: bool,
+    start_phase: f32,
+    free_run: bool,
+    frequency: f32,
+    bend: f32, // Frequency ratio from the pitch wheel
+    voice_ratios: [f32; MAX_UNISON],
+    voice_gains: [(f32, f32); MAX_UNISON],
+    sub_shape: SubShape,
+    sub_octave: u8,
+    sub_level: f32,
+    sub_phase: f32,
+    drift: f32, // Cents
+    drift_offsets: [f32; MAX_UNISON + 1],
+    drift_targets: [f32; MAX_UNISON + 1],
+    drift_seed: u32,
+    sample: Option<Arc<Sample>>,
+    sample_level: f32,
+    sample_position: f64, // Frames into the recording
+    pitch_env_amount: f32,
+    pitch_env_decay: f32,
+    glide_semitones: f32,
+    glide_start: f32,
+    glide_duration: f32,
+    glide_curve: GlideCurve,
+    mod_matrix: [ModSlot; MOD_SLOTS], // Fixed size so voices clone without allocating
+    mod_slots: usize,
+    mod_wheel: f32,
+    pressure: f32,
+    timbre: f32,
+    keytrack: f32,
+    random: f32,
+    samples: u64,
     pub adsr: ADSR,
+    lfo: Option<LFO>,
+    lfo2: Option<LFO>,
 }
 
 impl WavetableOscillator {
     pub fn new(
         sample_rate: u32,
-        wave_table: Vec<f32>,
+        wavetable: Arc<Wavetable>,
         volume: f32,
         adsr: ADSR,
     ) -> WavetableOscillator {
-        return WavetableOscillator {
+        let mut oscillator = WavetableOscillator {
             sample_rate,
-            wave_table,
-            index: 0.0,
+            wavetable,
+            wave_type: WaveType::Wavetable,
+            wave_level: 1.0,
+            pluck: Pluck::new(0.5, 1.0),
+            fm_ratio: 1.0,
+            fm_index: 0.0,
+            fm_env_amount: 0.0,
+            fm_env_decay: 0.0,
+            fm_phase: 0.0,
+            position: 0.0,
+            indices: [0.0; MAX_UNISON],
             index_increment: 0.0,
             volume,
+            velocity: 1.0,
+            pan: 0.0,
+            unison_voices: 1,
+            unison_detune: 0.0,
+            unison_spread: 0.0,
+            random_phase: false,
+            start_phase: 0.0,
+            free_run: false,
+            frequency: 0.0,
+            bend: 1.0,
+            voice_ratios: [1.0; MAX_UNISON],
+            voice_gains: [(0.0, 0.0); MAX_UNISON],
+            sub_shape: SubShape::Sine,
+            sub_octave: 1,
+            sub_level: 0.0,
+            sub_phase: 0.0,
+            drift: 0.0,
+            drift_offsets: [0.0; MAX_UNISON + 1],
+            drift_targets: [0.0; MAX_UNISON + 1],
+            drift_seed: 0,
+            sample: None,
+            sample_level: 1.0,
+            sample_position: 0.0,
+            pitch_env_amount: 0.0,
+            pitch_env_decay: 0.0,
+            glide_semitones: 0.0,
+            glide_start: 0.0,
+            glide_duration: 0.0,
+            glide_curve: GlideCurve::Linear,
+            mod_matrix: [ModSlot::default(); MOD_SLOTS],
+            mod_slots: 0,
+            mod_wheel: 0.0,
+            pressure: 0.0,
+            timbre: 0.0,
+            keytrack: 0.0,
+            random: 0.0,
+            samples: 0,
             adsr,
+            lfo: None,
+            lfo2: None,
         };
+        oscillator.update_unison();
+        oscillator
     }
 
     pub fn set_frequency(&mut self, frequency: f32) {
-        self.index_increment = frequency * self.wave_table.len() as f32 / self.sample_rate as f32;
+        self.frequency = frequency;
+        self.index_increment = frequency * TABLE_SIZE as f32 / self.sample_rate as f32;
+        self.keytrack = (frequency / MIDDLE_C).log2() / 5.0;
+    }
+
+    // Bends the note by `semitones` from the pitch wheel, taking effect on the
+    // next sample.
+    pub fn set_bend(&mut self, semitones: f32) {
+        self.bend = 2.0_f32.powf(semitones / 12.0);
+    }
+
+    // Moves a running oscillator to a new sample rate, keeping its pitch and
+    // how far through its envelope it has got.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        let ratio = self.sample_rate as f64 / sample_rate as f64;
+        self.index_increment *= ratio as f32;
+        self.samples = (self.samples as f64 / ratio) as u64;
+        self.sample_rate = sample_rate;
+        for lfo in self.lfo.iter_mut().chain(self.lfo2.iter_mut()) {
+            lfo.set_sample_rate(sample_rate);
+        }
+        self.pluck.set_sample_rate(sample_rate);
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn wave_type(&self) -> WaveType {
+        self.wave_type
+    }
+
+    pub fn set_wave_type(&mut self, wave_type: WaveType) {
+        self.wave_type = wave_type;
+    }
+
+    // Level of the wavetable, string or FM carrier, apart from the sub and
+    // sample layers.
+    pub fn set_wave_level(&mut self, level: f32) {
+        self.wave_level = level.max(0.0);
+    }
+
+    // Wanders each unison voice and the sub independently by up to `cents`,
+    // slowly, like an analog oscillator that never quite holds its pitch.
+    pub fn set_drift(&mut self, cents: f32) {
+        self.drift = cents.max(0.0);
+    }
+
+    // Damping and excitation brightness of the string, each 0.0 to 1.0.
+    pub fn set_pluck(&mut self, damping: f32, brightness: f32) {
+        self.pluck.set_tone(damping, brightness);
+    }
+
+    // Gives the string `line` as its delay line, of pluck::line_length, and
+    // hands back the one it had. Only Pluck voices need one.
+    pub fn swap_pluck_line(&mut self, line: &mut Vec<f32>) {
+        self.pluck.swap_line(line);
+    }
+
+    // The modulator runs at `ratio` times the note. Its index, in radians of
+    // phase deviation, starts at `index + env_amount` and decays back to
+    // `index` with `env_decay` seconds as the time constant.
+    pub fn set_fm(&mut self, ratio: f32, index: f32, env_amount: f32, env_decay: f32) {
+        self.fm_ratio = ratio.clamp(0.5, 16.0);
+        self.fm_index = index.max(0.0);
+        self.fm_env_amount = env_amount;
+        self.fm_env_decay = env_decay.max(0.0);
+    }
+
+    pub fn set_position(&mut self, position: f32) {
+        self.position = position.clamp(0.0, 1.0);
     }
 
     pub fn set_volume(&mut self, volume: f32) {
         self.volume = volume;
     }
 
-    fn get_sample(&mut self) -> f32 {
-        let sample = self.lerp();
-        self.index += self.index_increment;
-        self.index %= self.wave_table.len() as f32;
-        return sample * self.volume;
+    // Scales the volume by a note's velocity response, 0.0 to 1.0.
+    pub fn set_velocity(&mut self, velocity: f32) {
+        self.velocity = velocity;
+    }
+
+    // -1.0 is hard left, 1.0 hard right.
+    pub fn set_pan(&mut self, pan: f32) {
+        self.pan = pan.clamp(-1.0, 1.0);
+        self.update_unison();
+    }
+
+    // Stacks `voices` copies of the oscillator, detuned across `detune` cents
+    // in total and panned across `spread` (0.0 to 1.0) around the voice pan.
+    pub fn set_unison(&mut self, voices: usize, detune: f32, spread: f32) {
+        self.unison_voices = voices.clamp(1, MAX_UNISON);
+        self.unison_detune = detune.max(0.0);
+        self.unison_spread = spread.clamp(0.0, 1.0);
+        self.update_unison();
+    }
+
+    // Adds a sub-oscillator one or two octaves below the note.
+    pub fn set_sub(&mut self, shape: SubShape, octave: u8, level: f32) {
+        self.sub_shape = shape;
+        self.sub_octave = octave.clamp(1, 2);
+        self.sub_level = level.max(0.0);
+    }
+
+    // Layers a recording under the wavetable, played from the start of each
+    // note and pitched by key.
+    pub fn set_sample(&mut self, sample: Option<Arc<Sample>>) {
+        self.sample = sample;
+    }
+
+    pub fn set_sample_level(&mut self, level: f32) {
+        self.sample_level = level.max(0.0);
+    }
+
+    // Bends each note from `amount` semitones away back to its pitch, decaying
+    // exponentially with `decay` seconds as the time constant.
+    pub fn set_pitch_envelope(&mut self, amount: f32, decay: f32) {
+        self.pitch_env_amount = amount;
+        self.pitch_env_decay = decay.max(0.0);
+    }
+
+    // Slides into the note from `semitones` away over `duration` seconds,
+    // starting now, which for a mono voice may be mid-note.
+    pub fn set_glide(&mut self, semitones: f32, duration: f32, curve: GlideCurve) {
+        self.glide_semitones = semitones;
+        self.glide_start = self.time();
+        self.glide_duration = duration.max(0.0);
+        self.glide_curve = curve;
+    }
+
+    pub fn set_mod_matrix(&mut self, slots: &[ModSlot]) {
+        self.mod_slots = slots.len().min(MOD_SLOTS);
+        self.mod_matrix[..self.mod_slots].copy_from_slice(&slots[..self.mod_slots]);
+    }
+
+    // The mod wheel position, 0.0 to 1.0, taking effect on the next sample.
+    pub fn set_mod_wheel(&mut self, mod_wheel: f32) {
+        self.mod_wheel = mod_wheel;
+    }
+
+    // Aftertouch, 0.0 to 1.0, taking effect on the next sample.
+    pub fn set_pressure(&mut self, pressure: f32) {
+        self.pressure = pressure;
     }
 
-    fn lerp(&self) -> f32 {
-        let truncated_index = self.index as usize;
-        let next_index = (truncated_index + 1) % self.wave_table.len();
+    // CC 74, 0.0 to 1.0, taking effect on the next sample.
+    pub fn set_timbre(&mut self, timbre: f32) {
+        self.timbre = timbre;
+    }
+
+    pub fn set_random_phase(&mut self, random_phase: bool) {
+        self.random_phase = random_phase;
+    }
 
-        let next_index_weight = self.index - truncated_index as f32;
-        let truncated_index_weight = 1.0 - next_index_weight;
+    // Where in the cycle notes start, 0.0 to 1.0. Free-running notes ignore
+    // it and pick up as if the oscillator had never stopped.
+    pub fn set_start_phase(&mut self, start_phase: f32) {
+        self.start_phase = start_phase.rem_euclid(1.0);
+    }
 
-        return truncated_index_weight * self.wave_table[truncated_index]
-            + next_index_weight * self.wave_table[next_index];
+    pub fn set_free_run(&mut self, free_run: bool) {
+        self.free_run = free_run;
     }
 
-    pub fn clone(&self) -> WavetableOscillator {
-        return WavetableOscillator {
-            sample_rate: self.sample_rate,
-            wave_table: self.wave_table.clone(),
-            index: self.index,
-            index_increment: self.index_increment,
-            volume: self.volume,
-            adsr: self.adsr.clone(),
+    // Resets the unison phases for a note starting `time` seconds into the
+    // session, scattering them from `seed` when random phase is on so stacked
+    // voices don't start in lockstep.
+    pub fn reset_phase(&mut self, time: f64, mut seed: u32) {
+        let phase = if self.free_run {
+            (time * self.frequency as f64).fract() as f32
+        } else {
+            self.start_phase
         };
+        self.sub_phase = if self.free_run {
+            (time * self.frequency as f64 / (1 << self.sub_octave) as f64).fract() as f32
+        } else {
+            phase / (1 << self.sub_octave) as f32
+        };
+        self.sample_position = 0.0;
+        self.fm_phase = 0.0;
+        self.random = lfo::random(&mut seed);
+        self.drift_seed = seed;
+        for (offset, target) in self.drift_offsets.iter_mut().zip(self.drift_targets.iter_mut()) {
+            *target = lfo::random(&mut seed) * self.drift;
+            *offset = *target;
+        }
+        if self.wave_type == WaveType::Pluck {
+            self.pluck.excite(TABLE_SIZE as f32 / self.index_increment, &mut seed);
+        }
+        for index in self.indices.iter_mut() {
+            *index = if self.random_phase {
+                (lfo::random(&mut seed) * 0.5 + 0.5) * TABLE_SIZE as f32
+            } else {
+                phase * TABLE_SIZE as f32
+            };
+        }
     }
-}
 
-impl Iterator for WavetableOscillator {
-    type Item = f32;
+    fn update_unison(&mut self) {
+        // Normalise by sqrt(voices) since detuned voices sum incoherently.
+        let gain = 1.0 / (self.unison_voices as f32).sqrt();
+
+        let voices = self.voice_ratios.iter_mut().zip(self.voice_gains.iter_mut());
+        for (voice, (ratio, gains)) in voices.enumerate().take(self.unison_voices) {
+            // -1.0 for the lowest/leftmost voice up to 1.0 for the highest.
+            let offset = if self.unison_voices > 1 {
+                2.0 * voice as f32 / (self.unison_voices - 1) as f32 - 1.0
+            } else {
+                0.0
+            };
 
-    fn next(&mut self) -> Option<Self::Item> {
-        return Some(self.get_sample());
+            let cents = offset * self.unison_detune / 2.0;
+            *ratio = 2.0_f32.powf(cents / 1200.0);
+
+            // Constant-power pan law, so centred voices are 3 dB down per side.
+            let pan = (self.pan + offset * self.unison_spread).clamp(-1.0, 1.0);
+            let angle = (pan + 1.0) * FRAC_PI_4;
+            *gains = (angle.cos() * gain, angle.sin() * gain);
+        }
+    }
+
+    pub fn set_lfo(&mut self, lfo: Option<LFO>) {
+        self.lfo = lfo;
+    }
+
+    pub fn set_lfo2(&mut self, lfo: Option<LFO>) {
+        self.lfo2 = lfo;
+    }
+
+    // Changes the rate of whichever LFOs are given one, keeping their place
+    // in the cycle.
+    pub fn set_lfo_rates(&mut self, rate: Option<f32>, rate2: Option<f32>) {
+        for (lfo, rate) in [(self.lfo.as_mut(), rate), (self.lfo2.as_mut(), rate2)] {
+            if let (Some(lfo), Some(rate)) = (lfo, rate) {
+                lfo.set_rate(rate);
+            }
+        }
+    }
+
+    // Starts the LFOs for a note beginning `time` seconds into the session.
+    pub fn start_lfos(&mut self, time: f64) {
+        for lfo in self.lfo.iter_mut().chain(self.lfo2.iter_mut()) {
+            lfo.start(time);
+        }
+    }
+
+    // Envelope time comes from the number of frames rendered, not the wall
+    // clock, so it stays exact under underruns and when rendering offline.
+    fn time(&self) -> f32 {
+        (self.samples as f64 / self.sample_rate as f64) as f32
+    }
+
+    // Starts the envelope over for a key struck again while still sounding,
+    // plucking a string voice afresh so it doesn't restart on a dead string.
+    pub fn restart_envelope(&mut self, from_current: bool) {
+        let time = self.time();
+        self.adsr.restart(time, from_current);
+        if self.wave_type == WaveType::Pluck {
+            let mut seed = self.drift_seed ^ self.samples as u32;
+            self.pluck.excite(TABLE_SIZE as f32 / self.index_increment, &mut seed);
+        }
+    }
+
+    // Starts the envelope's release from wherever the voice has got to.
+    pub fn release(&mut self) {
+        let time = self.time();
+        self.adsr.stop(time);
+    }
+
+    // Fades the voice out over `duration` seconds, cutting short a long
+    // release or a one-shot.
+    pub fn fade_out(&mut self, duration: f32) {
+        let time = self.time();
+        self.adsr.fade_out(time, duration);
     }
-}
 
-impl Source for WavetableOscillator {
-    fn channels(&self) -> u16 {
-        return 1;
+    // Where the voice is in its envelope, scaled by velocity, for judging
+    // which is quietest.
+    pub fn level(&self) -> f32 {
+        self.adsr.value(self.time()) * self.velocity
     }
 
-    fn sample_rate(&self) -> u32 {
-        return self.sample_rate;
+    pub fn is_finished(&self) -> bool {
+        self.adsr.is_finished(self.time())
     }
 
-    fn current_frame_len(&self) -> Option<usize> {
-        return None;
+    // Adds interleaved stereo frames into `buffer`, stopping early once the
+    // envelope has finished. Returns the number of frames rendered.
+    pub fn render(&mut self, buffer: &mut [f32]) -> usize {
+        let mut frames = 0;
+        for frame in buffer.chunks_exact_mut(2) {
+            if self.is_finished() {
+                break;
+            }
+            let (left, right) = self.get_sample();
+            frame[0] += left;
+            frame[1] += right;
+            frames += 1;
+        }
+        frames
     }
 
-    fn total_duration(&self) -> Option<Duration> {
-        return None;
+    fn get_sample(&mut self) -> (f32, f32) {
+        let mut index_increment = self.index_increment * self.bend;
+        let time = self.time();
+        let envelope = self.adsr.value(time);
+        let mut volume = self.volume * self.velocity * envelope;
+        self.samples += 1;
+
+        if self.pitch_env_amount != 0.0 && self.pitch_env_decay > 0.0 {
+            let semitones = self.pitch_env_amount * (-time / self.pitch_env_decay).exp();
+            index_increment *= 2.0_f32.powf(semitones / 12.0);
+        }
+
+        if time - self.glide_start < self.glide_duration {
+            let progress = (time - self.glide_start) / self.glide_duration;
+            let remaining = match self.glide_curve {
+                GlideCurve::Linear => 1.0 - progress,
+                GlideCurve::Exponential => (-5.0 * progress).exp(),
+            };
+            index_increment *= 2.0_f32.powf(self.glide_semitones * remaining / 12.0);
+        }
+
+        if self.drift > 0.0 {
+            self.update_drift();
+        }
+
+        let lfo_value = apply_lfo(self.lfo.as_mut(), &mut index_increment, &mut volume);
+        let lfo2_value = apply_lfo(self.lfo2.as_mut(), &mut index_increment, &mut volume);
+
+        let mut position = self.position;
+        let mut pan_gains = (1.0, 1.0);
+        if self.mod_slots > 0 {
+            let sources = ModSources {
+                lfo: lfo_value,
+                lfo2: lfo2_value,
+                envelope,
+                velocity: self.velocity,
+                mod_wheel: self.mod_wheel,
+                pressure: self.pressure,
+                timbre: self.timbre,
+                keytrack: self.keytrack,
+                random: self.random,
+            };
+            let modulation = mod_matrix::evaluate(&self.mod_matrix[..self.mod_slots], &sources);
+            index_increment *= 2.0_f32.powf(modulation.pitch / 12.0);
+            position = (position + modulation.position).clamp(0.0, 1.0);
+            volume *= (1.0 + modulation.volume).max(0.0);
+            pan_gains = modulation.pan_gains();
+        }
+
+        let (mut left, mut right) = (0.0, 0.0);
+        match self.wave_type {
+            WaveType::Wavetable | WaveType::Fm => {
+                let phase_offset = match self.wave_type {
+                    WaveType::Fm => self.fm_sample(index_increment, time),
+                    _ => 0.0,
+                };
+                let voices = self.indices.iter_mut().zip(self.voice_ratios.iter().zip(self.voice_gains.iter()));
+                let drift = self.drift_offsets.iter();
+                for ((index, (ratio, (left_gain, right_gain))), drift) in voices.zip(drift).take(self.unison_voices) {
+                    let voice_increment = index_increment * ratio * (1.0 + drift * CENTS_TO_RATIO);
+                    let read_index = (*index + phase_offset).rem_euclid(TABLE_SIZE as f32);
+                    let sample = self.wavetable.sample(position, read_index, voice_increment);
+                    *index += voice_increment;
+                    *index %= TABLE_SIZE as f32;
+
+                    left += sample * left_gain;
+                    right += sample * right_gain;
+                }
+            }
+            // One string, without unison, panned by the same law.
+            WaveType::Pluck => {
+                let drift = 1.0 + self.drift_offsets[0] * CENTS_TO_RATIO;
+                let sample = self.pluck.next_sample(TABLE_SIZE as f32 / (index_increment * drift));
+                let angle = (self.pan + 1.0) * FRAC_PI_4;
+                left += sample * angle.cos();
+                right += sample * angle.sin();
+            }
+        }
+
+        left *= self.wave_level;
+        right *= self.wave_level;
+
+        if self.sub_level > 0.0 {
+            let drift = 1.0 + self.drift_offsets[SUB_DRIFT] * CENTS_TO_RATIO;
+            let sub = self.sub_sample(index_increment * drift) * self.sub_level * FRAC_1_SQRT_2;
+            left += sub;
+            right += sub;
+        }
+
+        if let Some(sample) = self.sample.as_ref().filter(|_| self.sample_level > 0.0) {
+            let value = sample.value(self.sample_position) * self.sample_level * FRAC_1_SQRT_2;
+            self.sample_position = sample.advance(self.sample_position, sample.increment(index_increment));
+            left += value;
+            right += value;
+        }
+
+        (left * volume * pan_gains.0, right * volume * pan_gains.1)
+    }
+
+    // Picks new targets every DRIFT_INTERVAL and glides towards them.
+    fn update_drift(&mut self) {
+        let interval = ((DRIFT_INTERVAL * self.sample_rate as f32) as u64).max(1);
+        if self.samples % interval == 0 {
+            for target in self.drift_targets.iter_mut() {
+                *target = lfo::random(&mut self.drift_seed) * self.drift;
+            }
+        }
+        let coefficient = 1.0 / interval as f32;
+        for (offset, target) in self.drift_offsets.iter_mut().zip(self.drift_targets.iter()) {
+            *offset += (target - *offset) * coefficient;
+        }
+    }
+
+    // How far the modulator pushes the carrier's read position this sample,
+    // in table indices.
+    fn fm_sample(&mut self, index_increment: f32, time: f32) -> f32 {
+        let mut index = self.fm_index;
+        if self.fm_env_decay > 0.0 {
+            index += self.fm_env_amount * (-time / self.fm_env_decay).exp();
+        }
+        let modulator = (2.0 * PI * self.fm_phase).sin();
+        self.fm_phase = (self.fm_phase + index_increment * self.fm_ratio / TABLE_SIZE as f32) % 1.0;
+        index.max(0.0) * modulator * TABLE_SIZE as f32 / (2.0 * PI)
+    }
+
+    fn sub_sample(&mut self, index_increment: f32) -> f32 {
+        let phase_increment = index_increment / TABLE_SIZE as f32 / (1 << self.sub_octave) as f32;
+        let phase = self.sub_phase;
+        self.sub_phase = (self.sub_phase + phase_increment) % 1.0;
+
+        match self.sub_shape {
+            SubShape::Sine => (2.0 * PI * phase).sin(),
+            SubShape::Square => {
+                let naive = if phase < 0.5 { 1.0 } else { -1.0 };
+                naive + poly_blep(phase, phase_increment)
+                    - poly_blep((phase + 0.5) % 1.0, phase_increment)
+            }
+        }
+    }
+}
+
+// Advances `lfo` and applies it to its own target, returning its raw value
+// for the mod matrix.
+fn apply_lfo(lfo: Option<&mut LFO>, index_increment: &mut f32, volume: &mut f32) -> f32 {
+    let lfo = match lfo {
+        Some(lfo) => lfo,
+        None => return 0.0,
+    };
+    let value = lfo.next_value();
+    match lfo.target() {
+        LfoTarget::Pitch => *index_increment *= 2.0_f32.powf(value * lfo.depth() / 12.0),
+        LfoTarget::Amplitude => *volume *= 1.0 - lfo.depth() * 0.5 * (1.0 - value),
+    }
+    value
+}
+
+// Smooths the step of a naive square at phase 0, for a wave advancing by
+// `increment` of a cycle per sample.
+fn poly_blep(phase: f32, increment: f32) -> f32 {
+    if phase < increment {
+        let t = phase / increment;
+        return 2.0 * t - t * t - 1.0;
+    } else if phase > 1.0 - increment {
+        let t = (phase - 1.0) / increment;
+        return t * t + 2.0 * t + 1.0;
     }
+    0.0
 }
@@ -0,0 +1,38 @@
+use std::time::{Duration, Instant};
+
+use wavetable_synth::clock::{MidiClock, TapTempo, MAX_TEMPO};
+
+#[test]
+fn midi_clock_measures_each_beat() {
+    let start = Instant::now();
+    let tick = Duration::from_secs_f64(0.5 / 24.0);
+    let mut clock = MidiClock::default();
+    let tempos: Vec<Option<f32>> = (0..=48).map(|n| clock.tick(start + tick * n)).collect();
+    // The first tick only starts the beat; each 24th after it ends one.
+    assert!(tempos.iter().enumerate().all(|(n, tempo)| tempo.is_some() == (n == 24 || n == 48)));
+    assert!((tempos[24].unwrap() - 120.0).abs() < 0.01);
+    assert!((tempos[48].unwrap() - 120.0).abs() < 0.01);
+
+    // After a reset the next tick starts a new beat.
+    clock.reset();
+    let later = start + Duration::from_secs(10);
+    let tempos: Vec<Option<f32>> = (0..=24).map(|n| clock.tick(later + tick * n * 2)).collect();
+    assert!(tempos[..24].iter().all(Option::is_none));
+    assert!((tempos[24].unwrap() - 60.0).abs() < 0.01);
+}
+
+#[test]
+fn tap_tempo_averages_recent_taps() {
+    let start = Instant::now();
+    let mut taps = TapTempo::default();
+    assert_eq!(taps.tap(start), None);
+    assert!((taps.tap(start + Duration::from_millis(500)).unwrap() - 120.0).abs() < 0.01);
+    // Four taps 0.5, 0.5 and 0.8 s apart average 0.6 s.
+    assert!((taps.tap(start + Duration::from_millis(1000)).unwrap() - 120.0).abs() < 0.01);
+    assert!((taps.tap(start + Duration::from_millis(1800)).unwrap() - 100.0).abs() < 0.01);
+
+    // A pause starts over.
+    let later = start + Duration::from_secs(10);
+    assert_eq!(taps.tap(later), None);
+    assert_eq!(taps.tap(later + Duration::from_millis(100)), Some(MAX_TEMPO));
+}
@@ -0,0 +1,51 @@
+use std::f32::consts::TAU;
+
+use wavetable_synth::compressor::{Compressor, CompressorSettings};
+
+// Peak in dBFS of a second of sine at `frequency` and `level` dBFS through
+// the compressor, once it has settled.
+fn output_level(settings: &CompressorSettings, frequency: f32, level: f32) -> f32 {
+    let mut compressor = Compressor::new(settings, 48000);
+    let amplitude = 10.0_f32.powf(level / 20.0);
+    let mut buffer: Vec<f32> =
+        (0..48000).flat_map(|frame| [amplitude * (TAU * frequency * frame as f32 / 48000.0).sin(); 2]).collect();
+    compressor.process(&mut buffer);
+    let peak = buffer[24000 * 2..].iter().fold(0.0_f32, |peak, sample| peak.max(sample.abs()));
+    20.0 * peak.log10()
+}
+
+#[test]
+fn compressor_turns_down_what_goes_over_the_threshold() {
+    let settings = CompressorSettings {
+        threshold: -20.0,
+        ratio: 4.0,
+        ..CompressorSettings::default()
+    };
+    // 20 dB over comes out 5 dB over.
+    let loud = output_level(&settings, 220.0, 0.0);
+    assert!((loud + 15.0).abs() < 1.0, "{} dBFS", loud);
+    let quiet = output_level(&settings, 220.0, -30.0);
+    assert!((quiet + 30.0).abs() < 0.01, "{} dBFS", quiet);
+
+    let makeup = CompressorSettings { makeup: 6.0, ..settings };
+    let quiet = output_level(&makeup, 220.0, -30.0);
+    assert!((quiet + 24.0).abs() < 0.01, "{} dBFS", quiet);
+}
+
+#[test]
+fn sidechain_highpass_lets_the_bass_through() {
+    let settings = CompressorSettings {
+        threshold: -20.0,
+        ratio: 4.0,
+        ..CompressorSettings::default()
+    };
+    let filtered = CompressorSettings {
+        sidechain_highpass: Some(300.0),
+        ..settings
+    };
+    let bass = output_level(&settings, 40.0, 0.0);
+    let filtered_bass = output_level(&filtered, 40.0, 0.0);
+    assert!(filtered_bass > bass + 6.0, "{} dBFS filtered, {} dBFS not", filtered_bass, bass);
+    // Treble is compressed either way.
+    assert!((output_level(&filtered, 5000.0, 0.0) - output_level(&settings, 5000.0, 0.0)).abs() < 0.5);
+}
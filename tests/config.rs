@@ -0,0 +1,24 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use wavetable_synth::config::Config;
+
+#[test]
+fn saved_config_loads_back_the_same() {
+    let dir = env::temp_dir().join(format!("wavetable_synth_config_{}", std::process::id()));
+    let path = dir.join("config.toml");
+    let config = Config {
+        audio_device: Some(String::from("USB Audio")),
+        sample_rate: Some(48000),
+        buffer_size: Some(128),
+        midi_ports: vec![String::from("Keystation")],
+        preset: Some(PathBuf::from("/presets/lead.json")),
+        master_tune: Some(-3.0),
+        ..Config::default()
+    };
+    config.save_to(&path).unwrap();
+    let loaded = Config::load_from(&path);
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(loaded.unwrap(), config);
+}
@@ -0,0 +1,57 @@
+use wavetable_synth::envelope::{EnvelopeMode, ADSR};
+
+#[test]
+fn restart_after_a_fade_keeps_the_release_and_mode() {
+    let mut envelope = ADSR::new(0.01, 0.1, 0.5, 1.0);
+    envelope.start(0.0);
+    envelope.fade_out(0.5, 0.005);
+    assert!(envelope.is_finished(0.506));
+    envelope.restart(1.0, false);
+    envelope.stop(2.0);
+    assert!((envelope.value(2.5) - 0.25).abs() < 1e-4, "{}", envelope.value(2.5));
+    assert!(!envelope.is_finished(2.5));
+
+    // A one-shot fades like any other, then goes back to ignoring note-offs.
+    let mut envelope = ADSR::new(0.01, 0.1, 0.5, 1.0);
+    envelope.set_mode(EnvelopeMode::OneShot);
+    envelope.start(0.0);
+    envelope.fade_out(0.05, 0.005);
+    assert!(envelope.is_finished(0.056));
+    envelope.restart(1.0, false);
+    envelope.stop(1.02);
+    assert!((envelope.value(1.01) - 1.0).abs() < 1e-3);
+    assert!(envelope.value(1.05) > 0.5);
+    assert!(envelope.is_finished(1.11));
+}
+
+#[test]
+fn loop_envelope_repeats_while_held() {
+    let mut envelope = ADSR::new(0.1, 0.1, 0.5, 0.2);
+    envelope.set_mode(EnvelopeMode::Loop);
+    envelope.start(0.0);
+    let at = |time: f32| envelope.value(time);
+    assert!((at(0.1) - 1.0).abs() < 1e-3 && (at(0.2) - 0.5).abs() < 1e-3);
+    // Later cycles rise from the sustain level.
+    assert!((at(0.25) - 0.75).abs() < 1e-3);
+    assert!((at(0.3) - 1.0).abs() < 1e-3 && (at(0.35) - 0.75).abs() < 1e-3 && (at(0.4) - 0.5).abs() < 1e-3);
+    assert!((at(1.05) - 0.75).abs() < 1e-3);
+
+    // Lets go from wherever the cycle is.
+    envelope.stop(1.05);
+    assert!((envelope.value(1.15) - 0.375).abs() < 1e-3);
+    assert!(envelope.is_finished(1.25));
+}
+
+#[test]
+fn one_shot_envelope_ignores_the_note_off() {
+    let mut envelope = ADSR::new(0.1, 0.2, 0.5, 1.0);
+    envelope.set_mode(EnvelopeMode::OneShot);
+    envelope.start(0.0);
+    envelope.stop(0.05);
+    assert!((envelope.value(0.1) - 1.0).abs() < 1e-3);
+    // Decays to silence, not the sustain level.
+    assert!((envelope.value(0.2) - 0.5).abs() < 1e-3);
+    assert!(!envelope.is_finished(0.29));
+    assert!(envelope.is_finished(0.3));
+    assert_eq!(envelope.value(0.35), 0.0);
+}
@@ -0,0 +1,47 @@
+use std::f32::consts::TAU;
+
+use wavetable_synth::eq::{Eq, EqBand, EqSettings};
+
+// dB change the EQ makes to a sine at `frequency`, measured once it has
+// settled.
+fn response(settings: &EqSettings, frequency: f32) -> f32 {
+    let mut eq = Eq::new(settings, 48000);
+    let mut buffer: Vec<f32> =
+        (0..48000).flat_map(|frame| [(TAU * frequency * frame as f32 / 48000.0).sin(); 2]).collect();
+    eq.process(&mut buffer);
+    let peak = buffer[24000 * 2..].iter().fold(0.0_f32, |peak, sample| peak.max(sample.abs()));
+    20.0 * peak.log10()
+}
+
+#[test]
+fn flat_eq_leaves_the_signal_alone() {
+    for frequency in [50.0, 1000.0, 15000.0] {
+        assert!(response(&EqSettings::default(), frequency).abs() < 0.05);
+    }
+}
+
+#[test]
+fn each_band_shapes_its_own_range() {
+    let defaults = EqSettings::default();
+    let low = EqSettings {
+        low: EqBand { gain: 6.0, ..defaults.low },
+        ..defaults
+    };
+    assert!((response(&low, 30.0) - 6.0).abs() < 0.5);
+    assert!(response(&low, 5000.0).abs() < 0.2);
+
+    let mid = EqSettings {
+        mid: EqBand { gain: -9.0, ..defaults.mid },
+        ..defaults
+    };
+    assert!((response(&mid, 1000.0) + 9.0).abs() < 0.2);
+    assert!(response(&mid, 50.0).abs() < 0.2);
+    assert!(response(&mid, 15000.0).abs() < 0.2);
+
+    let high = EqSettings {
+        high: EqBand { gain: 4.0, ..defaults.high },
+        ..defaults
+    };
+    assert!((response(&high, 18000.0) - 4.0).abs() < 0.5);
+    assert!(response(&high, 200.0).abs() < 0.2);
+}
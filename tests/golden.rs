@@ -0,0 +1,201 @@
+use std::env;
+use std::f32::consts::PI;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use wavetable_synth::preset::Preset;
+use wavetable_synth::render::{self, EventKind, MidiEvent};
+use wavetable_synth::tuning::Tuning;
+use wavetable_synth::wavetable::{self, Wavetable};
+use wavetable_synth::wavetable_oscillator::WaveType;
+
+// Renders short note sequences and compares their level and spectrum with
+// fingerprints stored in tests/golden/, so a DSP change that alters the sound
+// fails here instead of slipping through. After a deliberate change, rerun
+// with UPDATE_GOLDEN=1 to rewrite the fingerprints, and review the diff.
+
+const SAMPLE_RATE: u32 = 44100;
+const FFT_SIZE: usize = 8192;
+const BANDS: usize = 10; // Octaves from 20 Hz up
+const RMS_TOLERANCE: f32 = 0.01; // Relative
+const BAND_TOLERANCE: f32 = 0.5; // dB
+const BAND_FLOOR: f32 = -90.0; // dB; bands this quiet aren't compared
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Fingerprint {
+    frames: usize,
+    rms: [f32; 2],
+    bands: Vec<f32>, // Average power per octave, in dB
+}
+
+fn note(time: f64, key: u8, velocity: u8) -> MidiEvent {
+    MidiEvent { time, channel: 0, kind: EventKind::Note { key, velocity } }
+}
+
+// A chord held for half a second.
+fn chord() -> Vec<MidiEvent> {
+    let mut notes = Vec::new();
+    for key in [60, 64, 67] {
+        notes.push(note(0.0, key, 100));
+    }
+    for key in [60, 64, 67] {
+        notes.push(note(0.5, key, 0));
+    }
+    notes
+}
+
+// Three overlapping notes, for glide and retriggering.
+fn phrase() -> Vec<MidiEvent> {
+    vec![
+        note(0.0, 57, 90),
+        note(0.2, 64, 110),
+        note(0.25, 57, 0),
+        note(0.4, 69, 70),
+        note(0.45, 64, 0),
+        note(0.7, 69, 0),
+    ]
+}
+
+fn fingerprint(samples: &[f32]) -> Fingerprint {
+    let frames = samples.len() / 2;
+    let rms = [0, 1].map(|channel| {
+        let sum: f32 = samples.iter().skip(channel).step_by(2).map(|sample| sample * sample).sum();
+        (sum / frames.max(1) as f32).sqrt()
+    });
+
+    // Spectrum of the mono mix over the first FFT_SIZE frames, Hann-windowed.
+    let mut re: Vec<f32> = (0..FFT_SIZE)
+        .map(|i| {
+            let mono = samples.get(i * 2).map_or(0.0, |left| (left + samples[i * 2 + 1]) * 0.5);
+            let window = 0.5 - 0.5 * (2.0 * PI * i as f32 / FFT_SIZE as f32).cos();
+            mono * window
+        })
+        .collect();
+    let mut im = vec![0.0; FFT_SIZE];
+    wavetable::fft(&mut re, &mut im, false);
+
+    let bin_width = SAMPLE_RATE as f32 / FFT_SIZE as f32;
+    let bands = (0..BANDS)
+        .map(|band| {
+            let low = 20.0 * 2.0_f32.powi(band as i32);
+            let bins = (1..FFT_SIZE / 2).filter(|bin| (low..low * 2.0).contains(&(*bin as f32 * bin_width)));
+            let (power, count) = bins.fold((0.0, 0), |(power, count), bin| {
+                (power + re[bin] * re[bin] + im[bin] * im[bin], count + 1)
+            });
+            10.0 * (power / count.max(1) as f32).max(1e-20).log10()
+        })
+        .collect();
+
+    Fingerprint { frames, rms, bands }
+}
+
+fn check(name: &str, preset: Preset, events: &[MidiEvent]) {
+    let wavetable = Arc::new(Wavetable::basic_shapes());
+    let samples = render::render_events(events, &preset, &Tuning::default(), wavetable, None, SAMPLE_RATE);
+    let actual = fingerprint(&samples);
+
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "golden", &format!("{}.json", name)].iter().collect();
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, format!("{}\n", serde_json::to_string_pretty(&actual).unwrap())).unwrap();
+        return;
+    }
+    let json = fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("{}: {} (run with UPDATE_GOLDEN=1)", path.display(), e));
+    let expected: Fingerprint = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(actual.frames, expected.frames, "{}: length changed", name);
+    for (channel, (actual_rms, expected_rms)) in actual.rms.iter().zip(expected.rms.iter()).enumerate() {
+        assert!(
+            (actual_rms - expected_rms).abs() <= expected_rms * RMS_TOLERANCE,
+            "{}: channel {} RMS {} differs from {}",
+            name,
+            channel,
+            actual_rms,
+            expected_rms
+        );
+    }
+    for (band, (actual_db, expected_db)) in actual.bands.iter().zip(expected.bands.iter()).enumerate() {
+        if actual_db.max(*expected_db) < BAND_FLOOR {
+            continue;
+        }
+        assert!(
+            (actual_db - expected_db).abs() <= BAND_TOLERANCE,
+            "{}: band {} at {:.1} dB differs from {:.1} dB",
+            name,
+            band,
+            actual_db,
+            expected_db
+        );
+    }
+}
+
+#[test]
+fn init_chord() {
+    check("init_chord", Preset::default(), &chord());
+}
+
+#[test]
+fn unison_saw() {
+    let preset = Preset {
+        wavetable_position: 1.0,
+        unison_voices: 4,
+        unison_detune: 20.0,
+        unison_spread: 0.8,
+        sub_level: 0.5,
+        ..Preset::default()
+    };
+    check("unison_saw", preset, &chord());
+}
+
+#[test]
+fn glide_phrase() {
+    let preset = Preset {
+        wavetable_position: 0.7,
+        glide_time: 0.1,
+        pitch_env_amount: 12.0,
+        ..Preset::default()
+    };
+    check("glide_phrase", preset, &phrase());
+}
+
+#[test]
+fn pluck() {
+    let preset = Preset {
+        wave_type: WaveType::Pluck,
+        pluck_damping: 0.3,
+        release: 0.5,
+        ..Preset::default()
+    };
+    check("pluck", preset, &phrase());
+}
+
+#[test]
+fn fm_bell() {
+    let preset = Preset {
+        wave_type: WaveType::Fm,
+        wavetable_position: 0.0,
+        fm_ratio: 3.5,
+        fm_index: 0.5,
+        fm_env_amount: 4.0,
+        fm_env_decay: 0.2,
+        drift: 5.0,
+        ..Preset::default()
+    };
+    check("fm_bell", preset, &chord());
+}
+
+#[test]
+fn pitch_bend() {
+    let preset = Preset {
+        wavetable_position: 1.0,
+        pitch_bend_range: 12.0,
+        ..Preset::default()
+    };
+    let mut events = chord();
+    let bend = |time, bend| MidiEvent { time, channel: 0, kind: EventKind::PitchBend(bend) };
+    events.splice(3..3, [bend(0.1, 0.5), bend(0.2, -1.0), bend(0.3, 0.0)]);
+    check("pitch_bend", preset, &events);
+}
@@ -0,0 +1,81 @@
+use std::sync::Arc;
+
+use wavetable_synth::lfo::{LfoShape, LfoSync, LfoTarget, LFO};
+use wavetable_synth::preset::{LfoSettings, Preset};
+use wavetable_synth::synth::Synth;
+use wavetable_synth::wavetable::Wavetable;
+
+// One cycle of an LFO at 1 Hz, sampled 1000 times.
+fn cycle(shape: LfoShape) -> Vec<f32> {
+    let mut lfo = LFO::new(1000, shape, 1.0, 1.0, LfoTarget::Pitch);
+    lfo.start(0.0);
+    (0..1000).map(|_| lfo.next_value()).collect()
+}
+
+#[test]
+fn lfo_shapes_trace_their_cycle() {
+    let sine = cycle(LfoShape::Sine);
+    assert!(sine[0].abs() < 1e-6 && (sine[250] - 1.0).abs() < 1e-3 && (sine[750] + 1.0).abs() < 1e-3);
+    let triangle = cycle(LfoShape::Triangle);
+    assert!((triangle[0] + 1.0).abs() < 1e-3 && (triangle[500] - 1.0).abs() < 1e-2 && triangle[250].abs() < 1e-2);
+    let square = cycle(LfoShape::Square);
+    assert!(square[..499].iter().all(|&value| value == 1.0) && square[501..].iter().all(|&value| value == -1.0));
+
+    // Holds one random value for each cycle.
+    let mut lfo = LFO::new(1000, LfoShape::SampleAndHold, 10.0, 1.0, LfoTarget::Pitch);
+    lfo.start(0.0);
+    let held: Vec<f32> = (0..1000).map(|_| lfo.next_value()).collect();
+    let steps: Vec<f32> = held.chunks(100).skip(1).map(|cycle| cycle[1]).collect();
+    assert!(held.chunks(100).skip(1).all(|cycle| cycle[1..].iter().all(|&value| value == cycle[1])));
+    assert!(steps.iter().all(|value| (-1.0..=1.0).contains(value)));
+    assert!(steps.windows(2).any(|pair| pair[0] != pair[1]));
+}
+
+#[test]
+fn lfo_fades_in_and_runs_free_when_asked() {
+    let mut lfo = LFO::new(1000, LfoShape::Square, 1.0, 1.0, LfoTarget::Pitch);
+    lfo.set_fade_in(1.0);
+    lfo.start(0.0);
+    let faded: Vec<f32> = (0..500).map(|_| lfo.next_value()).collect();
+    assert!((faded[99] - 0.1).abs() < 1e-3 && (faded[399] - 0.4).abs() < 1e-3);
+
+    // A free-running LFO started a quarter of a second in is already at
+    // the top of its cycle; a retriggered one starts from zero.
+    let mut lfo = LFO::new(1000, LfoShape::Sine, 1.0, 1.0, LfoTarget::Pitch);
+    lfo.set_retrigger(false);
+    lfo.start(0.25);
+    assert!((lfo.next_value() - 1.0).abs() < 1e-3);
+    lfo.set_retrigger(true);
+    lfo.start(0.25);
+    assert!(lfo.next_value().abs() < 1e-3);
+
+    assert_eq!(LfoSync::Quarter.rate(120.0), 2.0);
+    assert_eq!(LfoSync::Eighth.rate(120.0), 4.0);
+}
+
+#[test]
+fn tremolo_gates_a_held_note() {
+    let preset = Preset {
+        wavetable_position: 0.0,
+        attack: 0.0,
+        sustain: 1.0,
+        lfo: Some(LfoSettings {
+            shape: LfoShape::Square,
+            rate: 2.0,
+            depth: 1.0,
+            target: LfoTarget::Amplitude,
+            sync: None,
+            free_run: false,
+            fade_in: 0.0,
+        }),
+        ..Preset::default()
+    };
+    let mut synth = Synth::new(preset, Arc::new(Wavetable::basic_shapes()), None, 44100);
+    synth.note_on(0, 69, 440.0, 127);
+    let mut buffer = vec![0.0; 22050 * 2];
+    synth.render(&mut buffer);
+    // Full level for the first quarter second, silent for the next.
+    let peak = |samples: &[f32]| samples.iter().fold(0.0_f32, |peak, sample| peak.max(sample.abs()));
+    assert!(peak(&buffer[1000 * 2..10000 * 2]) > 0.1);
+    assert!(peak(&buffer[12000 * 2..21000 * 2]) < 1e-3);
+}
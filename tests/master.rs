@@ -0,0 +1,86 @@
+use std::f32::consts::TAU;
+
+use wavetable_synth::master::{MasterBus, MasterSettings};
+
+// Interleaved stereo sine at `amplitude`, `frames` long.
+fn sine(amplitude: f32, frames: usize) -> Vec<f32> {
+    (0..frames).flat_map(|frame| [amplitude * (TAU * 220.0 * frame as f32 / 44100.0).sin(); 2]).collect()
+}
+
+#[test]
+fn limiter_leaves_quiet_signals_alone() {
+    let mut master = MasterBus::new(&MasterSettings::default(), 44100);
+    let input = sine(0.5, 4410);
+    let mut output = input.clone();
+    master.process(&mut output);
+    // Only delayed by the lookahead.
+    let lookahead = 66 * 2;
+    assert!(output[lookahead..].iter().zip(&input).all(|(out, sample)| (out - sample).abs() < 1e-6));
+    assert_eq!(master.meter().gain_reduction(), 0.0);
+}
+
+#[test]
+fn limiter_holds_peaks_under_the_ceiling() {
+    for soft_clip in [false, true] {
+        let settings = MasterSettings {
+            ceiling: -1.0,
+            soft_clip,
+            ..MasterSettings::default()
+        };
+        let ceiling = 10.0_f32.powf(-1.0 / 20.0);
+        let mut master = MasterBus::new(&settings, 44100);
+        let mut output = vec![0.0; 4410 * 2];
+        output.extend(sine(4.0, 4410));
+        output.extend(vec![0.0; 44100 * 2]);
+        let mut reductions = Vec::new();
+        for block in output.chunks_mut(128) {
+            master.process(block);
+            reductions.push(master.meter().gain_reduction());
+        }
+
+        let peak = output.iter().fold(0.0_f32, |peak, sample| peak.max(sample.abs()));
+        assert!(peak <= ceiling, "peak {} over the ceiling", peak);
+        // Limiting, rather than clipping, keeps the burst a sine unless
+        // it's asked to clip.
+        let burst = &output[(4410 + 200) * 2..(8820 - 200) * 2];
+        let flat = burst.iter().filter(|sample| sample.abs() >= ceiling * 0.999).count();
+        assert_eq!(flat > burst.len() / 20, soft_clip, "{} of {} samples at the ceiling", flat, burst.len());
+        let most = reductions.iter().fold(0.0_f32, |most, &reduction| most.max(reduction));
+        // The soft clipper already rounds the peaks off under the ceiling,
+        // leaving the limiter little to do.
+        let expected = if soft_clip { 0.0..1.0 } else { 10.0..15.0 };
+        assert!(expected.contains(&most), "{} dB of gain reduction", most);
+        assert_eq!(reductions.last(), Some(&0.0));
+    }
+}
+
+#[test]
+fn meters_follow_the_output() {
+    let mut master = MasterBus::new(&MasterSettings::default(), 44100);
+    let meter = master.meter();
+    // Offset the left channel and silence the right.
+    let mut output: Vec<f32> = sine(0.5, 44100 * 5)
+        .chunks(2)
+        .flat_map(|frame| [frame[0] + 0.1, 0.0])
+        .collect();
+    for block in output.chunks_mut(128) {
+        master.process(block);
+    }
+
+    let [peak_left, peak_right] = meter.take_peaks();
+    assert!((peak_left - 0.6).abs() < 1e-3, "left peak {}", peak_left);
+    assert_eq!(peak_right, 0.0);
+    assert_eq!(meter.take_peaks(), [0.0; 2]);
+    // sqrt(0.5² / 2 + 0.1²)
+    let [rms_left, rms_right] = meter.rms();
+    assert!((rms_left - 0.3674).abs() < 0.01, "left RMS {}", rms_left);
+    assert_eq!(rms_right, 0.0);
+    let [dc_left, dc_right] = meter.dc_offset();
+    assert!((dc_left - 0.1).abs() < 0.01, "left DC offset {}", dc_left);
+    assert_eq!(dc_right, 0.0);
+    assert!(!meter.take_clipped());
+
+    master.process(&mut sine(2.0, 64));
+    assert!(meter.take_clipped());
+    assert!(!meter.take_clipped());
+}
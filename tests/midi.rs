@@ -0,0 +1,57 @@
+use std::sync::Arc;
+
+use wavetable_synth::midi::VelocityCurve;
+use wavetable_synth::preset::Preset;
+use wavetable_synth::synth::Synth;
+use wavetable_synth::wavetable::Wavetable;
+
+const CURVES: [VelocityCurve; 5] = [
+    VelocityCurve::Linear,
+    VelocityCurve::Exponential,
+    VelocityCurve::Soft,
+    VelocityCurve::Hard,
+    VelocityCurve::Fixed,
+];
+
+#[test]
+fn velocity_curves_bend_between_silence_and_full() {
+    for curve in CURVES {
+        assert!((curve.apply(127) - 1.0).abs() < 1e-6, "{:?} at full velocity", curve);
+        let responses: Vec<f32> = (0..=127).map(|velocity| curve.apply(velocity)).collect();
+        assert!(responses.windows(2).all(|pair| pair[1] >= pair[0]), "{:?} isn't rising", curve);
+    }
+    assert_eq!(VelocityCurve::Linear.apply(0), 0.0);
+    assert!((VelocityCurve::Linear.apply(64) - 64.0 / 127.0).abs() < 1e-6);
+    assert!(CURVES.iter().all(|curve| *curve == VelocityCurve::Fixed || curve.apply(0) == 0.0));
+    assert_eq!(VelocityCurve::Fixed.apply(1), 1.0);
+
+    // Soft gets loud sooner than linear, hard and exponential later.
+    let half = |curve: VelocityCurve| curve.apply(64);
+    assert!(half(VelocityCurve::Soft) > half(VelocityCurve::Linear));
+    assert!(half(VelocityCurve::Linear) > half(VelocityCurve::Hard));
+    assert!(half(VelocityCurve::Hard) > half(VelocityCurve::Exponential));
+}
+
+#[test]
+fn velocity_curve_sets_the_note_level() {
+    let level = |velocity_curve, velocity| {
+        let preset = Preset {
+            wavetable_position: 0.0,
+            attack: 0.0,
+            sustain: 1.0,
+            velocity_curve,
+            ..Preset::default()
+        };
+        let mut synth = Synth::new(preset, Arc::new(Wavetable::basic_shapes()), None, 44100);
+        synth.note_on(0, 69, 440.0, velocity);
+        let mut buffer = vec![0.0; 4410 * 2];
+        synth.render(&mut buffer);
+        buffer.iter().fold(0.0_f32, |peak, sample| peak.max(sample.abs()))
+    };
+    let full = level(VelocityCurve::Linear, 127);
+    let soft = level(VelocityCurve::Soft, 32) / full;
+    let hard = level(VelocityCurve::Hard, 32) / full;
+    assert!((soft - (32.0_f32 / 127.0).sqrt()).abs() < 0.02, "soft curve at {}", soft);
+    assert!((hard - (32.0_f32 / 127.0).powi(2)).abs() < 0.02, "hard curve at {}", hard);
+    assert!((level(VelocityCurve::Fixed, 32) / full - 1.0).abs() < 0.02);
+}
@@ -0,0 +1,19 @@
+use std::env;
+use std::fs;
+
+use wavetable_synth::error::SynthError;
+use wavetable_synth::performance::Performance;
+
+#[test]
+fn layer_channel_must_be_a_midi_channel() {
+    let path = env::temp_dir().join(format!("wavetable_synth_{}_performance.json", std::process::id()));
+    for (channel, valid) in [(0, false), (1, true), (16, true), (17, false)] {
+        fs::write(&path, format!(r#"{{ "layers": [{{}}, {{ "channel": {} }}] }}"#, channel)).unwrap();
+        match Performance::load(&path) {
+            Ok(_) => assert!(valid, "accepted channel {}", channel),
+            Err(SynthError::PresetFormat(_)) => assert!(!valid, "rejected channel {}", channel),
+            Err(e) => panic!("channel {}: {}", channel, e),
+        }
+    }
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,87 @@
+use std::env;
+use std::fs;
+
+use wavetable_synth::params::Parameter;
+use wavetable_synth::preset::{Preset, PresetBank};
+
+fn preset(name: &str, author: &str, category: &str, tags: &[&str]) -> Preset {
+    Preset {
+        name: name.to_string(),
+        author: author.to_string(),
+        category: category.to_string(),
+        tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        ..Preset::default()
+    }
+}
+
+fn bank() -> PresetBank {
+    PresetBank {
+        name: String::from("Factory"),
+        presets: vec![
+            preset("Fat Bass", "Ann", "Bass", &["analog", "mono"]),
+            preset("Glass Pad", "Bo", "Pad", &["airy"]),
+            preset("Acid Line", "Ann", "bass", &["303"]),
+            preset("Bright Lead", "Cy", "Lead", &["Analog"]),
+        ],
+    }
+}
+
+// Program numbers of the presets a search finds.
+fn programs(results: Vec<(usize, &Preset)>) -> Vec<usize> {
+    results.into_iter().map(|(program, _)| program).collect()
+}
+
+#[test]
+fn bank_search_matches_name_author_and_tags_in_a_category() {
+    let bank = bank();
+    assert_eq!(bank.categories(), ["Bass", "Pad", "bass", "Lead"]);
+    assert_eq!(programs(bank.search(None, "")), [0, 1, 2, 3]);
+    assert_eq!(programs(bank.search(None, "PAD")), [1]);
+    assert_eq!(programs(bank.search(None, "ann")), [0, 2]);
+    assert_eq!(programs(bank.search(None, "analog")), [0, 3]);
+    assert_eq!(programs(bank.search(Some("BASS"), "")), [0, 2]);
+    assert_eq!(programs(bank.search(Some("Bass"), "analog")), [0]);
+    assert!(bank.search(Some("Keys"), "").is_empty());
+}
+
+#[test]
+fn bank_builds_from_a_directory_in_name_order() {
+    let dir = env::temp_dir().join(format!("wavetable_synth_bank_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let bank = bank();
+    for (file, preset) in ["b.json", "a.json", "d.json", "c.json"].iter().zip(&bank.presets) {
+        preset.save(&dir.join(file)).unwrap();
+    }
+    fs::write(dir.join("notes.txt"), "not a preset").unwrap();
+
+    let built = PresetBank::from_dir(&dir).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(built.name.starts_with("wavetable_synth_bank_"));
+    let names: Vec<&str> = built.presets.iter().map(|preset| preset.name.as_str()).collect();
+    assert_eq!(names, ["Glass Pad", "Fat Bass", "Bright Lead", "Acid Line"]);
+}
+
+#[test]
+fn randomize_stays_in_range_and_moves_by_the_amount() {
+    let base = Preset::default();
+    let randomized = |seed, amount| {
+        let mut preset = base.clone();
+        preset.randomize(seed, amount);
+        preset
+    };
+    let full = randomized(7, 1.0);
+    assert_eq!(full, randomized(7, 1.0));
+    assert_ne!(full, randomized(8, 1.0));
+    assert_eq!(randomized(7, 0.0), base);
+
+    let half = randomized(7, 0.5);
+    for parameter in Parameter::RANDOMIZED {
+        let (min, max) = parameter.random_range();
+        let value = parameter.get(&full);
+        assert!((min..=max).contains(&value), "{} at {} outside its random range", parameter.name(), value);
+        let expected = parameter.get(&base) + (value - parameter.get(&base)) * 0.5;
+        assert!((parameter.get(&half) - expected).abs() < 1e-4, "{} moved too far at half amount", parameter.name());
+    }
+    // Level, tuning and tempo are left alone.
+    assert_eq!((full.volume, full.fine_tune, full.tempo), (base.volume, base.fine_tune, base.tempo));
+}
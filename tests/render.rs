@@ -0,0 +1,53 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use wavetable_synth::render::{self, BitDepth};
+
+fn temp_wav(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("wavetable_synth_{}_{}.wav", std::process::id(), name))
+}
+
+#[test]
+fn normalize_brings_the_peak_just_under_full_scale() {
+    let mut samples = vec![0.1, -0.25, 0.2, 0.0];
+    render::normalize(&mut samples);
+    assert!((samples[1] + 0.989).abs() < 1e-6);
+    assert!((samples[0] / samples[1] + 0.4).abs() < 1e-6);
+
+    let mut silence = vec![0.0; 4];
+    render::normalize(&mut silence);
+    assert_eq!(silence, [0.0; 4]);
+}
+
+#[test]
+fn trim_drops_silent_frames_from_both_ends() {
+    // Frames: silent, quiet on the right only, loud, silent, loud, silent.
+    let mut samples = vec![0.0, 0.0, 0.0, 0.001, 0.5, 0.5, 0.0, 0.0, -0.5, 0.0, 0.00001, 0.0];
+    render::trim_silence(&mut samples);
+    assert_eq!(samples, [0.0, 0.001, 0.5, 0.5, 0.0, 0.0, -0.5, 0.0]);
+
+    let mut silence = vec![0.00001; 8];
+    render::trim_silence(&mut silence);
+    assert!(silence.is_empty());
+}
+
+#[test]
+fn dither_keeps_detail_below_one_lsb() {
+    // A third of a 16-bit step, which rounds away to nothing undithered.
+    let quiet = vec![0.33 / i16::MAX as f32; 20000];
+    let read = |dither| {
+        let path = temp_wav(if dither { "dithered" } else { "plain" });
+        render::write_wav(&path, &quiet, 44100, BitDepth::Int16, dither).unwrap();
+        let (samples, sample_rate) = render::read_wav(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(sample_rate, 44100);
+        samples
+    };
+    assert!(read(false).iter().all(|&sample| sample == 0.0));
+    let dithered = read(true);
+    let mean = dithered.iter().sum::<f32>() / dithered.len() as f32 * 32768.0;
+    assert!((mean - 0.33).abs() < 0.05, "dithered mean {} LSB", mean);
+    // The same every time.
+    assert_eq!(dithered, read(true));
+}
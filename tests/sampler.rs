@@ -0,0 +1,42 @@
+use std::env;
+use std::fs;
+
+use wavetable_synth::render::{self, BitDepth};
+use wavetable_synth::sampler::Sample;
+use wavetable_synth::wavetable::TABLE_SIZE;
+
+// A 100-frame ramp from 0.0 to 0.99, root key A4, with `loop_points`.
+fn ramp(loop_points: Option<(usize, usize)>) -> Sample {
+    let path = env::temp_dir().join(format!("wavetable_synth_ramp_{}_{:?}.wav", std::process::id(), loop_points));
+    let samples: Vec<f32> = (0..100).flat_map(|frame| [frame as f32 / 100.0; 2]).collect();
+    render::write_wav(&path, &samples, 44100, BitDepth::Float32, false).unwrap();
+    let sample = Sample::load(&path, 69, loop_points).unwrap();
+    fs::remove_file(&path).unwrap();
+    sample
+}
+
+#[test]
+fn sample_plays_once_without_loop_points() {
+    let sample = ramp(None);
+    // At its own pitch at the root key.
+    let increment = sample.increment(440.0 * TABLE_SIZE as f32 / 44100.0);
+    assert!((increment - 1.0).abs() < 1e-4);
+    assert!((sample.value(10.5) - 0.105).abs() < 1e-6);
+    assert_eq!(sample.advance(99.0, 2.0), 101.0);
+    assert_eq!(sample.value(101.0), 0.0);
+}
+
+#[test]
+fn sample_cycles_between_its_loop_points() {
+    let sample = ramp(Some((50, 80)));
+    assert_eq!(sample.advance(70.0, 5.0), 75.0);
+    assert_eq!(sample.advance(79.0, 2.0), 51.0);
+    assert_eq!(sample.advance(79.0, 62.0), 51.0);
+    // The last frame of the loop leads back into its start.
+    assert!((sample.value(79.5) - (0.79 + 0.5) / 2.0).abs() < 1e-6);
+
+    // Loop points that don't fit the file are dropped.
+    for loop_points in [(80, 50), (50, 101)] {
+        assert_eq!(ramp(Some(loop_points)).advance(99.0, 2.0), 101.0, "{:?} kept", loop_points);
+    }
+}
@@ -0,0 +1,699 @@
+use std::sync::Arc;
+
+use wavetable_synth::arp::ArpPattern;
+use wavetable_synth::lfo::{LfoShape, LfoSync, LfoTarget};
+use wavetable_synth::mod_matrix::{ModDestination, ModSlot, ModSource};
+use wavetable_synth::params::Parameter;
+use wavetable_synth::preset::{ArpSettings, LfoSettings, NotePriority, Preset, Retrigger, VoiceMode, VoiceSteal};
+use wavetable_synth::synth::{retunes, Synth, SynthEvent, MAX_VOICES};
+use wavetable_synth::tuning::Tuning;
+use wavetable_synth::wavetable::Wavetable;
+use wavetable_synth::wavetable_oscillator::{SubShape, WaveType};
+
+// Frequency of a steady tone in interleaved stereo, from the upward zero
+// crossings of the left channel.
+fn frequency(samples: &[f32], sample_rate: u32) -> f32 {
+    let left: Vec<f32> = samples.iter().step_by(2).copied().collect();
+    let crossings: Vec<usize> = (1..left.len()).filter(|&i| left[i - 1] < 0.0 && left[i] >= 0.0).collect();
+    let (first, last) = (crossings[0], crossings[crossings.len() - 1]);
+    (crossings.len() - 1) as f32 * sample_rate as f32 / (last - first) as f32
+}
+
+#[test]
+fn rate_change_keeps_pitch() {
+    let sine = Preset {
+        wavetable_position: 0.0,
+        ..Preset::default()
+    };
+    let mut synth = Synth::new(sine, Arc::new(Wavetable::basic_shapes()), None, 44100);
+    synth.note_on(0, 69, 440.0, 100);
+
+    let mut before = vec![0.0; 4410 * 2];
+    synth.render(&mut before);
+    synth.set_sample_rate(48000);
+    let mut after = vec![0.0; 4800 * 2];
+    synth.render(&mut after);
+
+    assert!(!synth.is_silent(), "the note stopped");
+    let (before, after) = (frequency(&before, 44100), frequency(&after, 48000));
+    assert!((before - 440.0).abs() < 0.5, "{} Hz before the change", before);
+    assert!((after - 440.0).abs() < 0.5, "{} Hz after the change", after);
+}
+
+// Largest step between neighbouring left samples.
+fn max_step(samples: &[f32]) -> f32 {
+    let left: Vec<f32> = samples.iter().step_by(2).copied().collect();
+    left.windows(2).fold(0.0, |step, pair| step.max((pair[1] - pair[0]).abs()))
+}
+
+#[test]
+fn stolen_voice_fades_out() {
+    let sine = Preset {
+        wavetable_position: 0.0,
+        ..Preset::default()
+    };
+    let mut synth = Synth::new(sine, Arc::new(Wavetable::basic_shapes()), None, 44100);
+    for key in 0..MAX_VOICES as u8 {
+        synth.note_on(0, key, 55.0, 100);
+    }
+    // A quarter of the way into a cycle, so the voices are at their peak
+    // when one is stolen.
+    let steal = 4209 * 2;
+    let mut output = vec![0.0; steal + 441 * 2];
+    synth.render(&mut output[..steal]);
+    synth.note_on(0, 100, 55.0, 100);
+    synth.render(&mut output[steal..]);
+
+    let (held, stolen) = (max_step(&output[steal / 2..steal]), max_step(&output[steal - 2..]));
+    assert!(stolen < held * 1.5, "step of {} after stealing, {} before", stolen, held);
+}
+
+#[test]
+fn sustain_pedal_holds_released_notes() {
+    let short = Preset {
+        release: 0.01,
+        ..Preset::default()
+    };
+    let mut synth = Synth::new(short, Arc::new(Wavetable::basic_shapes()), None, 44100);
+    let mut buffer = vec![0.0; 4410 * 2];
+    synth.handle(SynthEvent::Sustain(true));
+    synth.note_on(0, 60, 261.6, 100);
+    synth.note_off(0, 60);
+    synth.render(&mut buffer);
+    assert!(!synth.is_silent(), "note stopped with the pedal down");
+
+    // Sostenuto lifting doesn't let go of a sustained note.
+    synth.handle(SynthEvent::Sostenuto(true));
+    synth.handle(SynthEvent::Sostenuto(false));
+    synth.render(&mut buffer);
+    assert!(!synth.is_silent(), "note stopped when sostenuto lifted");
+
+    synth.handle(SynthEvent::Sustain(false));
+    synth.render(&mut buffer);
+    assert!(synth.is_silent(), "note kept sounding after the pedal lifted");
+
+    // All Notes Off releases sustained notes with the pedal still down.
+    synth.handle(SynthEvent::Sustain(true));
+    synth.note_on(0, 60, 261.6, 100);
+    synth.note_off(0, 60);
+    synth.handle(SynthEvent::AllNotesOff);
+    synth.render(&mut buffer);
+    assert!(synth.is_silent(), "note kept sounding after All Notes Off");
+}
+
+#[test]
+fn held_note_follows_a_tuning_change() {
+    let sine = Preset {
+        wavetable_position: 0.0,
+        ..Preset::default()
+    };
+    let mut tuning = Tuning::default();
+    let mut synth = Synth::new(sine, Arc::new(Wavetable::basic_shapes()), None, 44100);
+    synth.note_on(0, 69, tuning.frequency(69).unwrap(), 100);
+    let mut before = vec![0.0; 4410 * 2];
+    synth.render(&mut before);
+
+    // A4 retuned a semitone up by a real-time single note change.
+    let previous = tuning.clone();
+    assert!(tuning.apply_mts(&[0xF0, 0x7F, 0x7F, 0x08, 0x02, 0x00, 0x01, 69, 70, 0x00, 0x00, 0xF7]));
+    for event in retunes(&previous, &tuning) {
+        synth.handle(event);
+    }
+    let mut after = vec![0.0; 4410 * 2];
+    synth.render(&mut after);
+
+    let (before, after) = (frequency(&before, 44100), frequency(&after, 44100));
+    assert!((before - 440.0).abs() < 0.5, "{} Hz before the retune", before);
+    assert!((after - 466.16).abs() < 0.5, "{} Hz after the retune", after);
+}
+
+#[test]
+fn mod_wheel_reaches_held_notes() {
+    let wheel_vibrato = Preset {
+        wavetable_position: 0.0,
+        mod_matrix: vec![ModSlot {
+            source: ModSource::ModWheel,
+            destination: ModDestination::Pitch,
+            amount: 12.0,
+        }],
+        ..Preset::default()
+    };
+    let mut synth = Synth::new(wheel_vibrato, Arc::new(Wavetable::basic_shapes()), None, 44100);
+    synth.note_on(0, 69, 440.0, 100);
+    let mut before = vec![0.0; 4410 * 2];
+    synth.render(&mut before);
+
+    synth.handle(SynthEvent::ModWheel(1.0));
+    let mut after = vec![0.0; 4410 * 2];
+    synth.render(&mut after);
+
+    let (before, after) = (frequency(&before, 44100), frequency(&after, 44100));
+    assert!((before - 440.0).abs() < 0.5, "{} Hz with the wheel down", before);
+    assert!((after - 880.0).abs() < 1.0, "{} Hz with the wheel up", after);
+}
+
+fn rms(samples: &[f32]) -> f32 {
+    (samples.iter().map(|sample| sample * sample).sum::<f32>() / samples.len() as f32).sqrt()
+}
+
+#[test]
+fn restruck_pluck_is_plucked_again() {
+    let pluck = Preset {
+        wave_type: WaveType::Pluck,
+        retrigger: Retrigger::Always,
+        sustain: 1.0,
+        ..Preset::default()
+    };
+    let mut synth = Synth::new(pluck, Arc::new(Wavetable::basic_shapes()), None, 44100);
+    synth.note_on(0, 57, 220.0, 100);
+    let mut fresh = vec![0.0; 4410 * 2];
+    synth.render(&mut fresh);
+    // Long enough for the string to have died away.
+    let mut decay = vec![0.0; 44100 * 4 * 2];
+    synth.render(&mut decay);
+
+    synth.note_on(0, 57, 220.0, 100);
+    let mut restruck = vec![0.0; 4410 * 2];
+    synth.render(&mut restruck);
+    let (fresh, restruck) = (rms(&fresh), rms(&restruck));
+    assert!(restruck > fresh * 0.5, "RMS {} re-struck, {} fresh", restruck, fresh);
+}
+
+#[test]
+fn low_pluck_keeps_its_pitch_at_high_rates() {
+    let pluck = Preset {
+        wave_type: WaveType::Pluck,
+        sustain: 1.0,
+        ..Preset::default()
+    };
+    let sample_rate = 192000;
+    let mut synth = Synth::new(pluck, Arc::new(Wavetable::basic_shapes()), None, sample_rate);
+    // 27.5 Hz needs a string about 7000 samples long here.
+    synth.note_on(0, 21, 27.5, 100);
+    let mut output = vec![0.0; sample_rate as usize / 2 * 2];
+    synth.render(&mut output);
+
+    let left: Vec<f32> = output.iter().step_by(2).copied().collect();
+    let window = &left[left.len() - 20000..];
+    let correlation = |lag: usize| -> f32 { window[lag..].iter().zip(window).map(|(a, b)| a * b).sum() };
+    let period = (4000..10000).max_by(|&a, &b| correlation(a).total_cmp(&correlation(b))).unwrap();
+    let expected = sample_rate as f32 / 27.5;
+    assert!((period as f32 - expected).abs() < expected * 0.02, "period {} samples, expected {}", period, expected);
+}
+
+#[test]
+fn parameter_changes_match_a_rebuilt_patch() {
+    let lfo = LfoSettings {
+        shape: LfoShape::Triangle,
+        rate: 5.0,
+        depth: 0.5,
+        target: LfoTarget::Amplitude,
+        sync: None,
+        free_run: false,
+        fade_in: 0.0,
+    };
+    let base = Preset {
+        unison_voices: 3,
+        lfo: Some(lfo.clone()),
+        lfo2: Some(LfoSettings { target: LfoTarget::Pitch, sync: Some(LfoSync::Eighth), ..lfo }),
+        ..Preset::default()
+    };
+    let wavetable = Arc::new(Wavetable::basic_shapes());
+    for parameter in Parameter::ALL {
+        let value = parameter.from_normalized(0.7);
+        let mut changed = Synth::new(base.clone(), Arc::clone(&wavetable), None, 44100);
+        changed.set_parameter(parameter, value);
+        let mut preset = base.clone();
+        parameter.set(&mut preset, value);
+        let mut rebuilt = Synth::new(preset, Arc::clone(&wavetable), None, 44100);
+
+        let mut outputs = [vec![0.0; 4410 * 2], vec![0.0; 4410 * 2]];
+        for (synth, output) in [&mut changed, &mut rebuilt].into_iter().zip(outputs.iter_mut()) {
+            synth.note_on(0, 60, 261.63, 100);
+            synth.render(output);
+        }
+        assert!(outputs[0] == outputs[1], "{} differs from a patch built with it", parameter.name());
+    }
+}
+
+#[test]
+fn lfo_depth_scales_to_its_target() {
+    let vibrato = LfoSettings {
+        shape: LfoShape::Sine,
+        rate: 5.0,
+        depth: 6.0,
+        target: LfoTarget::Pitch,
+        sync: None,
+        free_run: false,
+        fade_in: 0.0,
+    };
+    let mut preset = Preset {
+        lfo: Some(vibrato.clone()),
+        ..Preset::default()
+    };
+    assert_eq!(Parameter::LfoDepth.get(&preset), 0.5);
+    // A controller at full travel reaches an octave, not a semitone.
+    Parameter::LfoDepth.set(&mut preset, Parameter::LfoDepth.from_normalized(1.0));
+    assert_eq!(preset.lfo.as_ref().map(|lfo| lfo.depth), Some(12.0));
+
+    preset.lfo = Some(LfoSettings { target: LfoTarget::Amplitude, ..vibrato });
+    Parameter::LfoDepth.set(&mut preset, 0.25);
+    assert_eq!(preset.lfo.as_ref().map(|lfo| lfo.depth), Some(0.25));
+}
+
+#[test]
+fn tempo_changes_reach_held_notes() {
+    let tremolo = LfoSettings {
+        shape: LfoShape::Square,
+        rate: 1.0,
+        depth: 1.0,
+        target: LfoTarget::Amplitude,
+        sync: Some(LfoSync::Quarter),
+        free_run: false,
+        fade_in: 0.0,
+    };
+    let preset = Preset {
+        lfo: Some(tremolo),
+        tempo: 120.0,
+        ..Preset::default()
+    };
+    let mut synth = Synth::new(preset, Arc::new(Wavetable::basic_shapes()), None, 44100);
+    synth.note_on(0, 69, 440.0, 100);
+    let mut warmup = vec![0.0; 4410 * 2];
+    synth.render(&mut warmup);
+
+    synth.set_parameter(Parameter::Tempo, 60.0);
+    let mut after = vec![0.0; 44100 * 2 * 2];
+    synth.render(&mut after);
+
+    // A quarter note at 60 bpm gates once a second, so two seconds of 10 ms
+    // blocks switch between loud and quiet about four times, not eight.
+    let levels: Vec<f32> = after.chunks(441 * 2).map(rms).collect();
+    let threshold = levels.iter().cloned().fold(0.0, f32::max) / 2.0;
+    let switches = levels.windows(2).filter(|pair| (pair[0] > threshold) != (pair[1] > threshold)).count();
+    assert!((3..=5).contains(&switches), "{} switches in two seconds", switches);
+}
+
+// Keys sounding after striking `keys` in turn at `velocities` on a synth
+// with two voices, stealing by `steal`.
+fn keys_after_stealing(steal: VoiceSteal, keys: &[u8], velocities: &[u8]) -> Vec<u8> {
+    let preset = Preset {
+        polyphony: 2,
+        voice_steal: steal,
+        attack: 0.0,
+        ..Preset::default()
+    };
+    let mut synth = Synth::new(preset, Arc::new(Wavetable::basic_shapes()), None, 44100);
+    let mut buffer = vec![0.0; 441 * 2];
+    for (&key, &velocity) in keys.iter().zip(velocities) {
+        synth.note_on(0, key, 440.0, velocity);
+        synth.render(&mut buffer);
+    }
+    let mut sounding: Vec<u8> = synth.sounding().map(|(_, key)| key).collect();
+    sounding.sort();
+    sounding
+}
+
+#[test]
+fn polyphony_limits_sounding_voices() {
+    assert_eq!(keys_after_stealing(VoiceSteal::Oldest, &[60, 64, 67, 71], &[100; 4]), [67, 71]);
+    let mut synth = Synth::new(Preset::default(), Arc::new(Wavetable::basic_shapes()), None, 44100);
+    synth.set_parameter(Parameter::Polyphony, 3.0);
+    for key in 60..66 {
+        synth.note_on(0, key, 440.0, 100);
+    }
+    assert_eq!(synth.sounding().count(), 3);
+}
+
+#[test]
+fn steal_modes_pick_their_voice() {
+    assert_eq!(keys_after_stealing(VoiceSteal::Oldest, &[60, 64, 67], &[127, 20, 100]), [64, 67]);
+    assert_eq!(keys_after_stealing(VoiceSteal::Quietest, &[60, 64, 67], &[127, 20, 100]), [60, 67]);
+    assert_eq!(keys_after_stealing(VoiceSteal::SameNote, &[60, 64, 64], &[100; 3]), [60, 64]);
+    assert_eq!(keys_after_stealing(VoiceSteal::SameNote, &[60, 64, 67], &[100; 3]), [64, 67]);
+}
+
+#[test]
+fn mono_voice_follows_held_keys() {
+    let mono = Preset {
+        wavetable_position: 0.0,
+        voice_mode: VoiceMode::Legato,
+        ..Preset::default()
+    };
+    let mut synth = Synth::new(mono.clone(), Arc::new(Wavetable::basic_shapes()), None, 44100);
+    let mut buffer = vec![0.0; 4410 * 2];
+    synth.note_on(0, 60, 261.63, 100);
+    synth.note_on(0, 64, 329.63, 100);
+    assert_eq!(synth.sounding().collect::<Vec<_>>(), [(0, 64)]);
+    synth.render(&mut buffer);
+    assert!((frequency(&buffer, 44100) - 329.63).abs() < 1.0);
+
+    // Letting go of the top note falls back to the one still held.
+    synth.note_off(0, 64);
+    assert_eq!(synth.sounding().collect::<Vec<_>>(), [(0, 60)]);
+    buffer.fill(0.0);
+    synth.render(&mut buffer);
+    assert!((frequency(&buffer, 44100) - 261.63).abs() < 1.0);
+    synth.note_off(0, 60);
+    let mut release = vec![0.0; 44100 * 2];
+    synth.render(&mut release);
+    assert!(synth.is_silent());
+
+    let low = Preset { note_priority: NotePriority::Low, ..mono };
+    let mut synth = Synth::new(low, Arc::new(Wavetable::basic_shapes()), None, 44100);
+    synth.note_on(0, 60, 261.63, 100);
+    synth.note_on(0, 64, 329.63, 100);
+    assert_eq!(synth.sounding().collect::<Vec<_>>(), [(0, 60)]);
+}
+
+#[test]
+fn only_mono_restarts_the_envelope_between_held_notes() {
+    let levels = |voice_mode| {
+        let preset = Preset {
+            voice_mode,
+            attack: 0.0,
+            decay: 0.1,
+            sustain: 0.2,
+            ..Preset::default()
+        };
+        let mut synth = Synth::new(preset, Arc::new(Wavetable::basic_shapes()), None, 44100);
+        synth.note_on(0, 60, 261.63, 100);
+        let mut settled = vec![0.0; 22050 * 2];
+        synth.render(&mut settled);
+        synth.note_on(0, 62, 293.66, 100);
+        let mut next = vec![0.0; 441 * 2];
+        synth.render(&mut next);
+        (rms(&settled[settled.len() - 882..]), rms(&next))
+    };
+    let (settled, next) = levels(VoiceMode::Legato);
+    assert!(next < settled * 1.5, "legato jumped from {} to {}", settled, next);
+    let (settled, next) = levels(VoiceMode::Mono);
+    assert!(next > settled * 2.5, "mono stayed at {} from {}", next, settled);
+}
+
+// Strikes a key, lets it settle at half level, then strikes it again under
+// `retrigger`, after letting go of it first if `released`. Returns the
+// voices sounding 50 ms later and the level 5 to 10 ms and 45 to 50 ms
+// after the second strike, relative to the settled level.
+fn restrike(retrigger: Retrigger, released: bool) -> (usize, f32, f32) {
+    let preset = Preset {
+        wavetable_position: 0.0,
+        attack: 0.1,
+        decay: 0.1,
+        sustain: 0.5,
+        release: 1.0,
+        retrigger,
+        ..Preset::default()
+    };
+    let mut synth = Synth::new(preset, Arc::new(Wavetable::basic_shapes()), None, 44100);
+    synth.note_on(0, 60, 1000.0, 100);
+    let mut settled = vec![0.0; 22050 * 2];
+    synth.render(&mut settled);
+    let settled = rms(&settled[settled.len() - 441 * 2..]);
+    if released {
+        synth.note_off(0, 60);
+        synth.render(&mut vec![0.0; 8820 * 2]);
+    }
+
+    synth.note_on(0, 60, 1000.0, 100);
+    let mut after = vec![0.0; 2205 * 2];
+    synth.render(&mut after);
+    let voices = synth.sounding().count();
+    let window = |ms: usize| rms(&after[ms * 441 / 10 * 2..(ms + 5) * 441 / 10 * 2]) / settled;
+    (voices, window(5), window(45))
+}
+
+#[test]
+fn retrigger_modes_pick_voices_and_envelope_starts() {
+    // A second voice alongside the first, which carries on.
+    let (voices, start, _) = restrike(Retrigger::Stack, false);
+    assert_eq!(voices, 2);
+    assert!(start > 0.9, "stacked voice cut the first to {}", start);
+
+    // One voice, starting over from silence.
+    let (voices, start, _) = restrike(Retrigger::Always, false);
+    assert_eq!(voices, 1);
+    assert!(start < 0.3, "always restarted at {}", start);
+
+    // One voice, rising from where it was.
+    let (voices, start, later) = restrike(Retrigger::FromCurrent, false);
+    assert_eq!(voices, 1);
+    assert!((0.95..1.2).contains(&start), "from current restarted at {}", start);
+    assert!(later > 1.3, "from current only reached {}", later);
+
+    // Carries on while held, and picks up from the release once let go.
+    let (voices, start, later) = restrike(Retrigger::Legato, false);
+    assert_eq!(voices, 1);
+    assert!((0.95..1.05).contains(&start) && (0.95..1.05).contains(&later), "legato moved to {} then {}", start, later);
+    let (voices, start, later) = restrike(Retrigger::Legato, true);
+    assert_eq!(voices, 1);
+    assert!((0.7..0.95).contains(&start), "released legato restarted at {}", start);
+    assert!(later > 1.0, "released legato only reached {}", later);
+
+    // The old voice fades out within a few ms and a fresh one starts from
+    // silence.
+    let (voices, start, _) = restrike(Retrigger::Cut, false);
+    assert_eq!(voices, 1);
+    assert!(start < 0.3, "cut restarted at {}", start);
+}
+
+// The key the arpeggiator plays on each of `steps` sixteenth-note steps at
+// 120 BPM, holding C, E and G.
+fn arp_keys(pattern: ArpPattern, octaves: u8, steps: usize) -> Vec<u8> {
+    let preset = Preset {
+        release: 0.0,
+        arp: Some(ArpSettings { pattern, octaves, rate: LfoSync::Sixteenth, gate: 0.5 }),
+        ..Preset::default()
+    };
+    let mut synth = Synth::new(preset, Arc::new(Wavetable::basic_shapes()), None, 44100);
+    for key in [64, 60, 67] {
+        synth.note_on(0, key, 440.0, 100);
+    }
+    let step = 5512;
+    let mut keys = Vec::new();
+    for _ in 0..steps {
+        let mut buffer = vec![0.0; 100 * 2];
+        synth.render(&mut buffer);
+        let sounding: Vec<u8> = synth.sounding().map(|(_, key)| key).collect();
+        assert_eq!(sounding.len(), 1, "{:?} sounding at once", sounding);
+        keys.push(sounding[0]);
+        let mut buffer = vec![0.0; (step - 100) * 2];
+        synth.render(&mut buffer);
+    }
+    keys
+}
+
+#[test]
+fn arpeggiator_steps_through_held_keys() {
+    assert_eq!(arp_keys(ArpPattern::Up, 1, 4), [60, 64, 67, 60]);
+    assert_eq!(arp_keys(ArpPattern::Down, 1, 4), [67, 64, 60, 67]);
+    assert_eq!(arp_keys(ArpPattern::UpDown, 2, 11), [60, 64, 67, 72, 76, 79, 76, 72, 67, 64, 60]);
+    let random = arp_keys(ArpPattern::Random, 2, 16);
+    assert!(random.iter().all(|key| [60, 64, 67, 72, 76, 79].contains(key)), "{:?}", random);
+}
+
+#[test]
+fn arpeggiator_stops_when_keys_are_let_go() {
+    let preset = Preset {
+        release: 0.0,
+        arp: Some(ArpSettings { pattern: ArpPattern::Up, octaves: 1, rate: LfoSync::Sixteenth, gate: 0.5 }),
+        ..Preset::default()
+    };
+    let mut synth = Synth::new(preset, Arc::new(Wavetable::basic_shapes()), None, 44100);
+    synth.note_on(0, 60, 261.63, 100);
+    let mut buffer = vec![0.0; 4410 * 2];
+    synth.render(&mut buffer);
+    synth.note_off(0, 60);
+    let mut buffer = vec![0.0; 44100 * 2];
+    synth.render(&mut buffer);
+    assert!(synth.is_silent());
+    assert!(rms(&buffer[buffer.len() / 2..]) == 0.0);
+}
+
+#[test]
+fn pressure_reaches_the_voices_it_is_meant_for() {
+    let pressure_fades = Preset {
+        mod_matrix: vec![ModSlot {
+            source: ModSource::Pressure,
+            destination: ModDestination::Volume,
+            amount: -1.0,
+        }],
+        attack: 0.0,
+        sustain: 1.0,
+        ..Preset::default()
+    };
+    let mut synth = Synth::new(pressure_fades, Arc::new(Wavetable::basic_shapes()), None, 44100);
+    let level = |synth: &mut Synth| {
+        let mut buffer = vec![0.0; 441 * 2];
+        synth.render(&mut buffer);
+        rms(&buffer)
+    };
+    synth.note_on(0, 60, 261.63, 100);
+    let untouched = level(&mut synth);
+    assert!(untouched > 0.01);
+
+    // Aftertouch on another key, or another channel, leaves the note alone.
+    synth.handle(SynthEvent::Pressure { channel: 0, key: Some(64), pressure: 1.0 });
+    synth.handle(SynthEvent::Pressure { channel: 1, key: None, pressure: 1.0 });
+    assert!((level(&mut synth) - untouched).abs() < untouched * 0.1);
+    synth.handle(SynthEvent::Pressure { channel: 0, key: Some(60), pressure: 1.0 });
+    assert!(level(&mut synth) < untouched * 0.01);
+
+    // Channel pressure reaches notes struck after it too.
+    synth.handle(SynthEvent::AllSoundOff);
+    level(&mut synth);
+    synth.handle(SynthEvent::Pressure { channel: 0, key: None, pressure: 1.0 });
+    synth.note_on(0, 67, 392.0, 100);
+    assert!(level(&mut synth) < untouched * 0.01);
+}
+
+#[test]
+fn mpe_bends_and_shapes_each_note_by_its_channel() {
+    let timbre_pitch = Preset {
+        wavetable_position: 0.0,
+        pitch_bend_range: 2.0,
+        mod_matrix: vec![ModSlot {
+            source: ModSource::Timbre,
+            destination: ModDestination::Pitch,
+            amount: 12.0,
+        }],
+        ..Preset::default()
+    };
+    let mut synth = Synth::new(timbre_pitch, Arc::new(Wavetable::basic_shapes()), None, 44100);
+    synth.handle(SynthEvent::Mpe(Some(48.0)));
+    synth.note_on(1, 69, 440.0, 100);
+    let pitch = |synth: &mut Synth| {
+        let mut buffer = vec![0.0; 4410 * 2];
+        synth.render(&mut buffer);
+        frequency(&buffer, 44100)
+    };
+
+    // Another member channel's bend and timbre leave the note alone.
+    synth.pitch_bend(2, 1.0);
+    synth.handle(SynthEvent::Timbre { channel: 2, timbre: 1.0 });
+    assert!((pitch(&mut synth) - 440.0).abs() < 0.5);
+    // A quarter of the way up the 48-semitone MPE range is an octave.
+    synth.pitch_bend(1, 0.25);
+    assert!((pitch(&mut synth) - 880.0).abs() < 1.0);
+    // The master channel bends every note by the preset's range.
+    synth.pitch_bend(0, 1.0);
+    let expected = 880.0 * 2.0_f32.powf(2.0 / 12.0);
+    assert!((pitch(&mut synth) - expected).abs() < 1.0);
+    synth.handle(SynthEvent::Timbre { channel: 1, timbre: 1.0 });
+    assert!((pitch(&mut synth) - expected * 2.0).abs() < 2.0);
+}
+
+#[test]
+fn sub_oscillator_plays_octaves_below_in_its_shape() {
+    let sub_only = |sub_shape, sub_octave| {
+        let preset = Preset {
+            wave_level: 0.0,
+            sub_shape,
+            sub_octave,
+            sub_level: 1.0,
+            attack: 0.0,
+            sustain: 1.0,
+            ..Preset::default()
+        };
+        let mut synth = Synth::new(preset, Arc::new(Wavetable::basic_shapes()), None, 44100);
+        synth.note_on(0, 69, 440.0, 127);
+        let mut buffer = vec![0.0; 8820 * 2];
+        synth.render(&mut buffer);
+        buffer
+    };
+    // Peak over RMS: about 1.41 for a sine and 1.0 for a square.
+    let crest = |buffer: &[f32]| buffer.iter().fold(0.0_f32, |peak, sample| peak.max(sample.abs())) / rms(buffer);
+
+    let sine = sub_only(SubShape::Sine, 1);
+    assert!((frequency(&sine, 44100) - 220.0).abs() < 1.0);
+    assert!((crest(&sine) - 2.0_f32.sqrt()).abs() < 0.05, "sine crest factor {}", crest(&sine));
+    let square = sub_only(SubShape::Square, 2);
+    assert!((frequency(&square, 44100) - 110.0).abs() < 1.0);
+    assert!(crest(&square) < 1.15, "square crest factor {}", crest(&square));
+}
+
+#[test]
+fn pitch_envelope_slides_into_the_note() {
+    let start_and_settled = |pitch_env_amount| {
+        let preset = Preset {
+            wavetable_position: 0.0,
+            pitch_env_amount,
+            pitch_env_decay: 0.05,
+            ..Preset::default()
+        };
+        let mut synth = Synth::new(preset, Arc::new(Wavetable::basic_shapes()), None, 44100);
+        synth.note_on(0, 69, 440.0, 100);
+        let mut buffer = vec![0.0; 22050 * 2];
+        synth.render(&mut buffer);
+        (frequency(&buffer[..441 * 2], 44100), frequency(&buffer[buffer.len() - 4410 * 2..], 44100))
+    };
+    // An octave up at the start, about 10 semitones on average over the
+    // first 10 ms.
+    let (start, settled) = start_and_settled(12.0);
+    assert!(start > 750.0, "started at {} Hz", start);
+    assert!((settled - 440.0).abs() < 1.0, "settled at {} Hz", settled);
+    let (start, settled) = start_and_settled(-12.0);
+    assert!(start < 260.0, "started at {} Hz", start);
+    assert!((settled - 440.0).abs() < 1.0, "settled at {} Hz", settled);
+}
+
+// Keys still sounding once `synth` has had 100 ms to finish any releases.
+fn sounding_after_release(synth: &mut Synth) -> Vec<(u8, u8)> {
+    synth.render(&mut vec![0.0; 4410 * 2]);
+    synth.sounding().collect()
+}
+
+#[test]
+fn latch_holds_notes_until_struck_again() {
+    let preset = Preset {
+        release: 0.01,
+        ..Preset::default()
+    };
+    let mut synth = Synth::new(preset, Arc::new(Wavetable::basic_shapes()), None, 44100);
+    synth.handle(SynthEvent::Latch(true));
+    synth.note_on(0, 60, 261.63, 100);
+    synth.note_on(0, 64, 329.63, 100);
+    synth.note_off(0, 60);
+    synth.note_off(0, 64);
+    assert_eq!(sounding_after_release(&mut synth), [(0, 60), (0, 64)]);
+
+    // Striking a latched key lets go of it.
+    synth.note_on(0, 60, 261.63, 100);
+    assert_eq!(sounding_after_release(&mut synth), [(0, 64)]);
+    // So does dropping the latch, after which note-offs count again.
+    synth.handle(SynthEvent::Latch(false));
+    assert!(sounding_after_release(&mut synth).is_empty());
+    synth.note_on(0, 67, 392.0, 100);
+    synth.note_off(0, 67);
+    assert!(sounding_after_release(&mut synth).is_empty());
+}
+
+#[test]
+fn panic_silences_everything_at_once() {
+    let preset = Preset {
+        release: 5.0,
+        ..Preset::default()
+    };
+    let mut synth = Synth::new(preset, Arc::new(Wavetable::basic_shapes()), None, 44100);
+    synth.handle(SynthEvent::Latch(true));
+    synth.handle(SynthEvent::Sustain(true));
+    for key in [60, 64, 67] {
+        synth.note_on(0, key, 261.63, 100);
+    }
+    synth.note_off(0, 67);
+    synth.render(&mut vec![0.0; 4410 * 2]);
+
+    synth.handle(SynthEvent::AllSoundOff);
+    // Faded out within a few ms despite the long release, the latch and
+    // the pedal.
+    let mut after = vec![0.0; 441 * 2];
+    synth.render(&mut after);
+    assert!(synth.is_silent());
+    assert!(after[after.len() - 100..].iter().all(|&sample| sample == 0.0));
+
+    // The latch and pedal are dropped with them.
+    synth.note_on(0, 60, 261.63, 100);
+    synth.note_off(0, 60);
+    synth.render(&mut vec![0.0; 44100 * 6 * 2]);
+    assert!(synth.is_silent());
+}
@@ -0,0 +1,44 @@
+use wavetable_synth::preset::Preset;
+use wavetable_synth::sysex::{self, SysexMessage};
+
+// A name outside ASCII, so the packed data has high bits to carry.
+fn preset() -> Preset {
+    Preset {
+        name: String::from("Glöckchen ♪"),
+        wavetable_position: 0.3,
+        unison_voices: 3,
+        release: 1.25,
+        ..Preset::default()
+    }
+}
+
+#[test]
+fn preset_dump_round_trips() {
+    let preset = preset();
+    let message = sysex::encode_preset(&preset).unwrap();
+    assert!(message[1..message.len() - 1].iter().all(|byte| byte & 0x80 == 0), "data byte above 0x7F");
+
+    match sysex::decode(&message) {
+        Ok(Some(SysexMessage::PresetDump(decoded))) => assert_eq!(*decoded, preset),
+        _ => panic!("not decoded as a preset dump"),
+    }
+}
+
+#[test]
+fn corrupted_dump_is_rejected() {
+    let mut message = sysex::encode_preset(&preset()).unwrap();
+    let checksum = message.len() - 2;
+    message[checksum] = (message[checksum] + 1) & 0x7F;
+    assert!(sysex::decode(&message).is_err());
+
+    // A changed data byte no longer matches the checksum either.
+    let mut message = sysex::encode_preset(&preset()).unwrap();
+    message[10] ^= 0x01;
+    assert!(sysex::decode(&message).is_err());
+}
+
+#[test]
+fn dump_request_is_recognised() {
+    assert!(matches!(sysex::decode(&sysex::dump_request()), Ok(Some(SysexMessage::DumpRequest))));
+    assert!(matches!(sysex::decode(&[0xF0, 0x41, 0x10, 0x42, 0xF7]), Ok(None)));
+}
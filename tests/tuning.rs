@@ -0,0 +1,165 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use wavetable_synth::tuning::{self, KeyboardMapping, Tuning};
+
+// Writes `text` to a file of its own in the temp directory, for the loaders
+// that only take paths.
+fn temp_file(name: &str, text: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("wavetable_synth_{}_{}", std::process::id(), name));
+    fs::write(&path, text).unwrap();
+    path
+}
+
+fn assert_close(actual: Option<f32>, expected: f32) {
+    let actual = actual.unwrap_or_else(|| panic!("key unmapped, expected {} Hz", expected));
+    assert!((actual - expected).abs() < 0.01, "{} Hz, expected {} Hz", actual, expected);
+}
+
+#[test]
+fn scl_reads_cents_and_ratios_around_comments() {
+    let text = "! meantone.scl\n!\nA test scale\n 3\n!\n 100.0\n 3/2  a fifth\n2\n";
+    let scale = tuning::parse_scl(text).unwrap();
+    assert_eq!(scale.len(), 3);
+    assert!((scale[0] - 100.0).abs() < 1e-9);
+    assert!((scale[1] - 701.955).abs() < 1e-3);
+    assert!((scale[2] - 1200.0).abs() < 1e-9);
+}
+
+#[test]
+fn scl_rejects_malformed_files() {
+    for text in [
+        "",                              // No note count
+        "Empty\n 0\n",                   // No notes
+        "Short\n 2\n 100.0\n",           // Fewer pitches than promised
+        "Zero\n 1\n 3/0\n",              // Infinite ratio
+        "Negative\n 1\n -3/2\n",         // Negative ratio
+        "Words\n 1\n a fifth\n",         // Not a pitch
+        "Count\n many\n 100.0\n",        // Not a count
+    ] {
+        assert!(tuning::parse_scl(text).is_err(), "accepted {:?}", text);
+    }
+}
+
+#[test]
+fn kbm_maps_white_keys_from_its_reference() {
+    let text = "! white keys only\n12\n0\n127\n60\n69\n432.0\n12\n! mapping\n0\nx\n2\nx\n4\n5\nx\n7\nx\n9\nx\n11\n";
+    let mut tuning = Tuning::default();
+    let path = temp_file("white.kbm", text);
+    tuning.load_mapping(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_close(tuning.frequency(69), 432.0);
+    assert_close(tuning.frequency(81), 864.0);
+    assert_close(tuning.frequency(60), 432.0 * 2.0_f32.powf(-9.0 / 12.0));
+    assert_eq!(tuning.frequency(61), None);
+}
+
+#[test]
+fn kbm_rejects_malformed_files() {
+    for text in [
+        "12\n0\n127\n",                         // Stops before the reference
+        "12\nlow\n127\n60\n69\n440.0\n12\n",    // Not a key
+        "0\n0\n127\n60\n69\n0.0\n0\n",          // No reference frequency
+        "0\n0\n127\n60\n69\n-440.0\n0\n",       // Negative reference frequency
+        "1\n0\n127\n60\n69\n440.0\n0\nthird\n", // Not a degree
+    ] {
+        assert!(KeyboardMapping::parse(text).is_err(), "accepted {:?}", text);
+    }
+}
+
+#[test]
+fn reference_frequency_must_be_positive() {
+    let mut tuning = Tuning::default();
+    for frequency in [0.0, -440.0, f32::NAN, f32::INFINITY] {
+        assert!(tuning.set_reference_frequency(frequency).is_err(), "accepted {}", frequency);
+    }
+    assert_close(tuning.frequency(69), 440.0);
+
+    tuning.set_reference_frequency(415.0).unwrap();
+    assert_close(tuning.frequency(69), 415.0);
+}
+
+// 12-TET with A4 at 440 Hz, as MTS defines its note table.
+fn equal(note: f32) -> f32 {
+    440.0 * 2.0_f32.powf((note - 69.0) / 12.0)
+}
+
+#[test]
+fn mts_single_note_changes() {
+    let mut tuning = Tuning::default();
+    let message = [
+        0xF0, 0x7F, 0x7F, 0x08, 0x02, 0x00, 0x03, // Real-time, program 0, three changes
+        60, 69, 0x00, 0x00, // C4 to A4
+        61, 69, 0x40, 0x00, // C#4 to half way from A4 to A#4
+        62, 0x7F, 0x7F, 0x7F, // D4 left alone
+        0xF7,
+    ];
+    assert!(tuning.apply_mts(&message));
+    assert_close(tuning.frequency(60), 440.0);
+    assert_close(tuning.frequency(61), equal(69.5));
+    assert_close(tuning.frequency(62), equal(62.0));
+
+    // The same with a bank number, and only the fraction's low seven bits.
+    let message = [0xF0, 0x7E, 0x7F, 0x08, 0x07, 0x00, 0x00, 0x01, 64, 64, 0x00, 0x40, 0xF7];
+    assert!(tuning.apply_mts(&message));
+    assert_close(tuning.frequency(64), equal(64.0 + 64.0 / 16384.0));
+}
+
+#[test]
+fn mts_bulk_dump() {
+    let mut message = vec![0xF0, 0x7E, 0x7F, 0x08, 0x01, 0x00];
+    message.extend(b"Test tuning     ");
+    for key in 0..128u8 {
+        match key {
+            0 => message.extend([0x7F, 0x7F, 0x7F]),
+            69 => message.extend([70, 0x00, 0x00]),
+            72 => message.extend([72, 0x20, 0x00]),
+            _ => message.extend([key, 0x00, 0x00]),
+        }
+    }
+    message.extend([0x00, 0xF7]); // Checksum, not checked
+    let mut tuning = Tuning::default();
+    assert!(tuning.apply_mts(&message));
+
+    assert_close(tuning.frequency(0), equal(0.0));
+    assert_close(tuning.frequency(60), equal(60.0));
+    assert_close(tuning.frequency(69), equal(70.0));
+    assert_close(tuning.frequency(72), equal(72.25));
+
+    // A dump cut short is left alone.
+    let mut tuning = Tuning::default();
+    assert!(!tuning.apply_mts(&[&message[..100], &[0xF7]].concat()));
+    assert_close(tuning.frequency(69), 440.0);
+}
+
+#[test]
+fn mts_scale_octave_tunings() {
+    // One byte per pitch class, in cents from -64 to 63: A up 14 cents.
+    let mut offsets = [64; 12];
+    offsets[9] = 78;
+    let message = [&[0xF0, 0x7E, 0x7F, 0x08, 0x08, 0x03, 0x7F, 0x7F][..], &offsets, &[0xF7]].concat();
+    let mut tuning = Tuning::default();
+    assert!(tuning.apply_mts(&message));
+    assert_close(tuning.frequency(57), equal(57.14));
+    assert_close(tuning.frequency(69), equal(69.14));
+    assert_close(tuning.frequency(60), equal(60.0));
+
+    // Two bytes per pitch class, 14 bits across -100 to 100 cents: A up 50.
+    let mut offsets = [0x40, 0x00].repeat(12);
+    offsets[18] = 0x60;
+    let message = [&[0xF0, 0x7F, 0x7F, 0x08, 0x09, 0x03, 0x7F, 0x7F][..], &offsets, &[0xF7]].concat();
+    let mut tuning = Tuning::default();
+    assert!(tuning.apply_mts(&message));
+    assert_close(tuning.frequency(69), equal(69.5));
+    assert_close(tuning.frequency(70), equal(70.0));
+}
+
+#[test]
+fn mts_ignores_other_sysex() {
+    let mut tuning = Tuning::default();
+    assert!(!tuning.apply_mts(&[0xF0, 0x7D, 0x00, 0x01, 0xF7]));
+    assert!(!tuning.apply_mts(&[0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7]));
+    assert_close(tuning.frequency(69), 440.0);
+}
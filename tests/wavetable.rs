@@ -0,0 +1,39 @@
+use std::env;
+use std::f32::consts::TAU;
+use std::fs;
+
+use wavetable_synth::error::SynthError;
+use wavetable_synth::render::{self, BitDepth};
+use wavetable_synth::wavetable::{Wavetable, TABLE_SIZE};
+
+#[test]
+fn wav_folder_loads_as_frames_in_name_order() {
+    let dir = env::temp_dir().join(format!("wavetable_synth_frames_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    // A quiet sine and an inverted one at another length, both as stereo.
+    let cycle = |length: usize, amplitude: f32| -> Vec<f32> {
+        (0..length).flat_map(|i| [amplitude * (TAU * i as f32 / length as f32).sin(); 2]).collect()
+    };
+    render::write_wav(&dir.join("b.wav"), &cycle(300, -0.8), 44100, BitDepth::Float32, false).unwrap();
+    render::write_wav(&dir.join("a.WAV"), &cycle(600, 0.5), 44100, BitDepth::Int16, false).unwrap();
+    fs::write(dir.join("readme.txt"), "not a frame").unwrap();
+    let wavetable = Wavetable::load_dir(&dir);
+    fs::remove_dir_all(&dir).unwrap();
+
+    let wavetable = wavetable.unwrap();
+    assert_eq!(wavetable.frame_count(), 2);
+    // Resampled to the table and peak-normalised.
+    let quarter = (TABLE_SIZE / 4) as f32;
+    assert!((wavetable.sample(0.0, quarter, 1.0) - 1.0).abs() < 0.01);
+    assert!((wavetable.sample(1.0, quarter, 1.0) + 1.0).abs() < 0.01);
+    assert!(wavetable.sample(0.0, 0.0, 1.0).abs() < 0.01);
+}
+
+#[test]
+fn wav_folder_without_frames_is_an_error() {
+    let dir = env::temp_dir().join(format!("wavetable_synth_no_frames_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let wavetable = Wavetable::load_dir(&dir);
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(matches!(wavetable, Err(SynthError::NoWavetableFrames(_))));
+}